@@ -0,0 +1,359 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use crate::config::*;
+use crate::model::*;
+use crate::sync::*;
+
+// stdioサーバを起動してinitializeとtools/listが通るかを確かめる
+
+/// initializeで要求するプロトコルバージョン
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// 応答を待つ既定の時間
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ヘルスチェックの結果
+#[derive(Debug)]
+pub struct CheckReport {
+    pub protocol_version: String,
+    pub server_name: String,
+    pub server_version: String,
+    /// toolsのcapabilityが無いサーバはNone
+    pub tool_count: Option<usize>,
+    /// 起動からtools/listの応答までの時間
+    pub latency: Duration,
+}
+
+impl CheckReport {
+    pub fn summary(&self) -> String {
+        let tools = match self.tool_count {
+            Some(n) => format!("{} tools", n),
+            None => "no tools".to_string(),
+        };
+        format!(
+            "ok (protocol {}, {} {}, {}, {}ms)",
+            self.protocol_version, self.server_name, self.server_version, tools, self.latency.as_millis(),
+        )
+    }
+}
+
+/// 起動したサーバとのJSON-RPCのやり取り（改行区切り）
+struct Session {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    lines: Receiver<String>,
+    stderr: Arc<Mutex<Vec<String>>>,
+    deadline: Instant,
+    timeout: Duration,
+}
+
+impl Session {
+    fn send(&mut self, message: &Value) -> Result<(), Box<dyn std::error::Error>> {
+        let stdin = self.stdin.as_mut().ok_or("stdin is closed")?;
+        writeln!(stdin, "{}", message)?;
+        stdin.flush()?;
+        Ok(())
+    }
+
+    /// リクエストを送り、同じidの応答のresultを返す。JSONでない行やサーバからの通知は読み飛ばす
+    fn request(&mut self, id: u64, method: &str, params: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        self.send(&json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .map_err(|_| self.exited(method))?;
+        loop {
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(remaining) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!("timed out after {}ms waiting for {}", self.timeout.as_millis(), method).into());
+                },
+                Err(RecvTimeoutError::Disconnected) => return Err(self.exited(method)),
+            };
+            let Ok(message) = serde_json::from_str::<Value>(&line) else { continue };
+            if message.get("method").is_some() || message.get("id") != Some(&json!(id)) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                let text = error["message"].as_str().map(|s| s.to_string()).unwrap_or_else(|| error.to_string());
+                return Err(format!("{} failed: {}", method, text).into());
+            }
+            return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    /// 応答前に終了したサーバのエラー。stderrの最後の行を添える
+    /// stdoutを閉じても終了しないサーバは期限まで待ってから終了させる
+    fn exited(&mut self, method: &str) -> Box<dyn std::error::Error> {
+        let status = loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => break format!("exited ({})", status),
+                Ok(None) if Instant::now() < self.deadline => std::thread::sleep(Duration::from_millis(10)),
+                Ok(None) => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    break "closed stdout".to_string();
+                },
+                Err(e) => break format!("exited ({})", e),
+            }
+        };
+        let last = self.stderr.lock().ok().and_then(|lines| lines.iter().rev().find(|l| !l.trim().is_empty()).cloned());
+        match last {
+            Some(line) => format!("server {} before responding to {}: {}", status, method, line.trim()).into(),
+            None => format!("server {} before responding to {}", status, method).into(),
+        }
+    }
+}
+
+impl Drop for Session {
+    // stdinを閉じてから終了させる（stdinのEOFで終了するサーバの子プロセスも残らないように）
+    fn drop(&mut self) {
+        self.stdin.take();
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// stdioサーバを起動し、initialize、notifications/initialized、tools/listの順にやり取りする
+/// serverは展開済みの定義を受け取る
+pub fn check_server(server: &McpServerConfig, timeout: Duration) -> Result<CheckReport, Box<dyn std::error::Error>> {
+    if !server.transport.is_stdio() {
+        return Err(format!("{} servers cannot be checked (only stdio)", server.transport.as_str()).into());
+    }
+    if server.command.is_empty() {
+        return Err("command is empty".into());
+    }
+    let start = Instant::now();
+    let mut command = Command::new(&server.command);
+    command.args(&server.args)
+        .envs(&server.env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = &server.cwd {
+        command.current_dir(cwd);
+    }
+    let mut child = command.spawn().map_err(|e| format!("failed to start {}: {}", server.command, e))?;
+    let stdin = child.stdin.take();
+    let stdout = child.stdout.take().ok_or("failed to open stdout")?;
+    let stderr_pipe = child.stderr.take().ok_or("failed to open stderr")?;
+
+    let (tx, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let stderr = Arc::new(Mutex::new(Vec::new()));
+    let stderr_lines = Arc::clone(&stderr);
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr_pipe).lines() {
+            let Ok(line) = line else { break };
+            if let Ok(mut lines) = stderr_lines.lock() {
+                lines.push(line);
+            }
+        }
+    });
+
+    let mut session = Session { child, stdin, lines, stderr, deadline: start + timeout, timeout };
+    let init = session.request(1, "initialize", json!({
+        "protocolVersion": PROTOCOL_VERSION,
+        "capabilities": {},
+        "clientInfo": {"name": "mcpallete", "version": env!("CARGO_PKG_VERSION")},
+    }))?;
+    session.send(&json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))?;
+
+    let tool_count = if init["capabilities"].get("tools").is_some() {
+        // nextCursorが返る間はページをたどる
+        let mut count = 0;
+        let mut cursor: Option<Value> = None;
+        for id in 2.. {
+            let params = match &cursor {
+                Some(cursor) => json!({"cursor": cursor}),
+                None => json!({}),
+            };
+            let result = session.request(id, "tools/list", params)?;
+            count += result["tools"].as_array().map_or(0, |tools| tools.len());
+            cursor = result.get("nextCursor").filter(|c| !c.is_null()).cloned();
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Some(count)
+    } else {
+        None
+    };
+
+    let text = |v: &Value| v.as_str().unwrap_or("-").to_string();
+    Ok(CheckReport {
+        protocol_version: text(&init["protocolVersion"]),
+        server_name: text(&init["serverInfo"]["name"]),
+        server_version: text(&init["serverInfo"]["version"]),
+        tool_count,
+        latency: start.elapsed(),
+    })
+}
+
+/// サーバ名と、展開済みの定義（展開できなかった場合はエラー）
+pub type CheckTarget = (String, Result<McpServerConfig, String>);
+
+/// サーバ名とチェックの結果
+pub type CheckResult = (String, Result<CheckReport, String>);
+
+/// チェックするサーバを展開する。envを指定するとその環境のoverridesとenvFilesも使う
+/// 変数が足りないサーバがあっても他のサーバはチェックできるよう、サーバごとに結果を返す
+pub fn check_targets(cfg: &McpServersConfig, names: &[String], env: Option<&str>) -> Result<Vec<CheckTarget>, Box<dyn std::error::Error>> {
+    let (ctx, env_cfg) = match env {
+        Some(env_name) => {
+            let env_cfg = cfg.environments.get(env_name)
+                .ok_or_else(|| format!("unknown environment: {}", env_name))?;
+            (env_context(cfg, env_cfg, &RefCache::default())?, Some(env_cfg))
+        },
+        None => (VarContext::from_env_files(cfg.env_files.iter())?, None),
+    };
+    let skip: Vec<&str> = env_cfg.map(|e| e.skip_expand.iter().map(|s| s.as_str()).collect()).unwrap_or_default();
+    let mut targets = Vec::new();
+    for name in names {
+        let servers = enabled_server_defs(cfg, std::slice::from_ref(name));
+        if servers.is_empty() {
+            return Err(format!("unknown server: {}", name).into());
+        }
+        let servers = match env_cfg {
+            Some(env_cfg) => apply_overrides(env_cfg, servers),
+            None => servers,
+        };
+        let server = expand_servers(servers, &ctx, &skip, SecretRefs::Expand)
+            .map_err(|e| e.to_string())
+            .and_then(|mut servers| servers.remove(name).ok_or_else(|| format!("unknown server: {}", name)));
+        targets.push((name.clone(), server));
+    }
+    Ok(targets)
+}
+
+/// 展開済みのサーバを並行してチェックする（結果はtargetsの順）
+pub fn check_all(targets: Vec<CheckTarget>, timeout: Duration) -> Vec<CheckResult> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = targets.into_iter().map(|(name, server)| {
+            let handle = scope.spawn(move || server.and_then(|s| check_server(&s, timeout).map_err(|e| e.to_string())));
+            (name, handle)
+        }).collect();
+        handles.into_iter()
+            .map(|(name, handle)| (name, handle.join().unwrap_or_else(|_| Err("check panicked".to_string()))))
+            .collect()
+    })
+}
+
+/// モックサーバの1メッセージへの応答（通知にはNone）
+pub fn mock_response(message: &Value, tools: usize) -> Option<Value> {
+    let id = message.get("id")?.clone();
+    let result = match message["method"].as_str().unwrap_or_default() {
+        "initialize" => json!({
+            "protocolVersion": message["params"]["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+            "capabilities": {"tools": {}},
+            "serverInfo": {"name": "mcpallete-mock", "version": env!("CARGO_PKG_VERSION")},
+        }),
+        "tools/list" => json!({
+            "tools": (1..=tools).map(|i| json!({
+                "name": format!("tool{}", i),
+                "description": format!("Mock tool {}", i),
+                "inputSchema": {"type": "object"},
+            })).collect::<Vec<_>>(),
+        }),
+        "ping" => json!({}),
+        method => return Some(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": -32601, "message": format!("Method not found: {}", method)},
+        })),
+    };
+    Some(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+}
+
+/// checkのテスト用の最小限のMCPサーバ。stdinのEOFで終了する
+pub fn run_mock_server(tools: usize, delay: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    for line in stdin.lock().lines() {
+        let Ok(message) = serde_json::from_str::<Value>(&line?) else { continue };
+        if let Some(response) = mock_response(&message, tools) {
+            std::thread::sleep(delay);
+            writeln!(stdout, "{}", response)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_mock_response() {
+        let init = mock_response(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2025-03-26"}}), 2).unwrap();
+        assert_eq!(init["id"], 1);
+        assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+        assert_eq!(init["result"]["serverInfo"]["name"], "mcpallete-mock");
+        let tools = mock_response(&json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}), 3).unwrap();
+        assert_eq!(tools["result"]["tools"].as_array().unwrap().len(), 3);
+        assert!(mock_response(&json!({"jsonrpc": "2.0", "method": "notifications/initialized"}), 2).is_none());
+        let unknown = mock_response(&json!({"jsonrpc": "2.0", "id": 3, "method": "resources/list"}), 2).unwrap();
+        assert_eq!(unknown["error"]["code"], -32601);
+    }
+
+    #[test]
+    fn test_check_server_errors() {
+        let remote = McpServerConfig { transport: Transport::Http, url: Some("https://example.com".to_string()), ..Default::default() };
+        assert!(check_server(&remote, CHECK_TIMEOUT).unwrap_err().to_string().contains("only stdio"));
+        let missing = McpServerConfig { command: "/nonexistent/mcpallete-test".to_string(), ..Default::default() };
+        assert!(check_server(&missing, CHECK_TIMEOUT).unwrap_err().to_string().starts_with("failed to start"));
+        // 応答せずに終了するサーバはstderrの内容を添える
+        let exits = McpServerConfig {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo 'missing API key' >&2; exit 3".to_string()],
+            ..Default::default()
+        };
+        let err = check_server(&exits, CHECK_TIMEOUT).unwrap_err().to_string();
+        assert!(err.contains("before responding to initialize"), "{}", err);
+        // stdoutを閉じて動き続けるサーバもtimeoutで打ち切る
+        let closes = McpServerConfig {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "exec >&-; sleep 5".to_string()],
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let err = check_server(&closes, Duration::from_millis(200)).unwrap_err().to_string();
+        assert_eq!(err, "server closed stdout before responding to initialize");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        let hangs = McpServerConfig { command: "sleep".to_string(), args: vec!["5".to_string()], ..Default::default() };
+        let err = check_server(&hangs, Duration::from_millis(200)).unwrap_err().to_string();
+        assert_eq!(err, "timed out after 200ms waiting for initialize");
+    }
+
+    #[test]
+    fn test_check_targets_expands_per_server() {
+        unsafe { std::env::set_var("CHECK_TEST_TOKEN", "t0ken"); }
+        let cfg = McpServersConfig {
+            mcp_servers: HashMap::from([
+                ("a".to_string(), McpServerConfig {
+                    command: "echo".to_string(),
+                    env: HashMap::from([("TOKEN".to_string(), "$CHECK_TEST_TOKEN".to_string())]),
+                    ..Default::default()
+                }),
+                ("b".to_string(), McpServerConfig { command: "${CHECK_TEST_UNSET:?needed}".to_string(), ..Default::default() }),
+            ]),
+            ..Default::default()
+        };
+        let targets = check_targets(&cfg, &["a".to_string(), "b".to_string()], None).unwrap();
+        assert_eq!(targets[0].1.as_ref().unwrap().env["TOKEN"], "t0ken");
+        assert!(targets[1].1.is_err());
+        assert!(check_targets(&cfg, &["nope".to_string()], None).is_err());
+        assert!(check_targets(&cfg, &["a".to_string()], Some("nope")).is_err());
+    }
+}
@@ -0,0 +1,469 @@
+use clap::{Parser, Subcommand};
+use crate::check::*;
+use crate::config::*;
+use crate::diff::*;
+use crate::history::*;
+use crate::import::*;
+use crate::model::*;
+use crate::secret::*;
+use crate::sync::*;
+
+#[derive(Parser)]
+#[command(name = "mcpallete", version, about = "Manage MCP server configurations per environment")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List environments and servers, or the enabled state of one environment
+    List {
+        env: Option<String>,
+    },
+    /// Enable servers in an environment and write its target config
+    Enable {
+        env: String,
+        #[arg(required = true)]
+        servers: Vec<String>,
+    },
+    /// Disable servers in an environment and write its target config
+    Disable {
+        env: String,
+        #[arg(required = true)]
+        servers: Vec<String>,
+    },
+    /// Replace the enabled servers of an environment with a preset
+    ApplyPreset {
+        env: String,
+        preset: String,
+    },
+    /// Show server definitions with secret values masked
+    Show {
+        server: Option<String>,
+        /// Show the values written for this environment (overrides and expansion applied)
+        #[arg(long)]
+        env: Option<String>,
+    },
+    /// Regenerate the target config of an environment from its enable list
+    Sync {
+        #[arg(required_unless_present = "all")]
+        env: Option<String>,
+        /// Regenerate every environment (continues past failures and reports each one)
+        #[arg(long, conflicts_with = "env")]
+        all: bool,
+        /// Show the changes to the target config without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List the server sets previously applied to an environment (0 is the latest)
+    History {
+        env: String,
+    },
+    /// Restore the server set applied n syncs ago (default 1, the one before the latest)
+    Rollback {
+        env: String,
+        #[arg(default_value_t = 1)]
+        n: usize,
+    },
+    /// Check whether the target configs still match the enabled servers (exits non-zero on drift)
+    Status {
+        env: Option<String>,
+    },
+    /// Start stdio servers and check the MCP initialize handshake and tools/list
+    Check {
+        /// Server to check (defaults to all servers, or the enabled ones with --env)
+        server: Option<String>,
+        /// Apply the overrides and envFiles of this environment
+        #[arg(long)]
+        env: Option<String>,
+        /// Time to wait for each server, in milliseconds
+        #[arg(long, default_value_t = CHECK_TIMEOUT.as_millis() as u64)]
+        timeout: u64,
+    },
+    /// Minimal MCP server over stdio, used to test check
+    #[command(hide = true)]
+    MockServer {
+        #[arg(long, default_value_t = 2)]
+        tools: usize,
+        /// Delay before each response, in milliseconds
+        #[arg(long, default_value_t = 0)]
+        delay_ms: u64,
+    },
+    /// Import the servers of an existing client config and enable them in an environment
+    Import {
+        mode: String,
+        path: std::path::PathBuf,
+        /// Environment to create or update (defaults to the mode name)
+        #[arg(long)]
+        env: Option<String>,
+        /// Project path for claude_code
+        #[arg(long)]
+        project: Option<String>,
+        /// What to do when a server with the same name but a different definition exists
+        #[arg(long, value_enum, default_value_t = Conflict::Skip)]
+        on_conflict: Conflict,
+    },
+}
+
+pub fn run_command(command: Command) -> Result<(), Box<dyn std::error::Error>> {
+    // モックサーバはユーザーの設定に触れずに起動する
+    if let Command::MockServer { tools, delay_ms } = command {
+        return run_mock_server(tools, std::time::Duration::from_millis(delay_ms));
+    }
+    ensure_config()?;
+    let mut cfg = load_config()?;
+    match command {
+        Command::List { env } => {
+            print!("{}", list_output(&cfg, env.as_deref())?);
+        },
+        Command::Enable { env, servers } => {
+            let mut enabled = enabled_servers(&cfg, &env)?;
+            for server in servers {
+                if !cfg.mcp_servers.contains_key(&server) {
+                    return Err(format!("unknown server: {}", server).into());
+                }
+                if !enabled.contains(&server) {
+                    enabled.push(server);
+                }
+            }
+            print_warnings(&sync_environment(&mut cfg, &env, enabled, &RefCache::default())?);
+        },
+        Command::Disable { env, servers } => {
+            let mut enabled = enabled_servers(&cfg, &env)?;
+            enabled.retain(|s| !servers.contains(s));
+            print_warnings(&sync_environment(&mut cfg, &env, enabled, &RefCache::default())?);
+        },
+        Command::ApplyPreset { env, preset } => {
+            let env_cfg = cfg.environments.get(&env)
+                .ok_or_else(|| format!("unknown environment: {}", env))?;
+            let enabled = env_cfg.preset.as_ref()
+                .and_then(|p| p.get(&preset))
+                .cloned()
+                .ok_or_else(|| format!("unknown preset: {}", preset))?;
+            print_warnings(&sync_environment(&mut cfg, &env, enabled, &RefCache::default())?);
+        },
+        Command::Show { server, env } => {
+            print!("{}", show_output(&cfg, server.as_deref(), env.as_deref())?);
+        },
+        Command::Sync { all: true, dry_run: true, .. } => {
+            for line in preview_all_lines(&cfg, &RefCache::default()) {
+                println!("{}", line);
+            }
+        },
+        Command::Sync { all: true, dry_run: false, .. } => {
+            let (out, failed) = sync_all_output(&sync_all(&mut cfg, &RefCache::default()));
+            print!("{}", out);
+            if !failed.is_empty() {
+                return Err(format!("failed to sync {}", failed.join(", ")).into());
+            }
+        },
+        Command::Sync { env, dry_run: true, .. } => {
+            let env = env.ok_or("an environment or --all is required")?;
+            let enabled = enabled_servers(&cfg, &env)?;
+            match plan_environment(&cfg, &env, &enabled, &RefCache::default())? {
+                Some(plan) => {
+                    for line in preview_lines(&plan) {
+                        println!("{}", line);
+                    }
+                },
+                None => println!("{} has no target file (mode is not set)", env),
+            }
+        },
+        Command::Sync { env, dry_run: false, .. } => {
+            let env = env.ok_or("an environment or --all is required")?;
+            let enabled = enabled_servers(&cfg, &env)?;
+            print_warnings(&sync_environment(&mut cfg, &env, enabled, &RefCache::default())?);
+        },
+        Command::History { env } => {
+            enabled_servers(&cfg, &env)?;
+            let entries = load_history(&get_history_dir(), &env)?;
+            if entries.is_empty() {
+                println!("No history for {}", env);
+            }
+            for (n, entry) in entries.iter().enumerate() {
+                println!("{}", history_line(n, entry));
+            }
+        },
+        Command::Rollback { env, n } => {
+            print_warnings(&rollback_environment(&mut cfg, &env, n)?);
+        },
+        Command::Status { env } => {
+            let (out, failed) = status_output(&cfg, env.as_deref())?;
+            print!("{}", out);
+            if !failed.is_empty() {
+                return Err(format!("drift detected in {}", failed.join(", ")).into());
+            }
+        },
+        Command::Check { server, env, timeout } => {
+            let names = match (server, env.as_deref()) {
+                (Some(server), _) => vec![server],
+                (None, Some(env)) => enabled_servers(&cfg, env)?,
+                (None, None) => {
+                    let mut names: Vec<String> = cfg.mcp_servers.keys().cloned().collect();
+                    names.sort();
+                    names
+                },
+            };
+            let targets = check_targets(&cfg, &names, env.as_deref())?;
+            let (out, failed) = check_output(&check_all(targets, std::time::Duration::from_millis(timeout)));
+            print!("{}", out);
+            if !failed.is_empty() {
+                return Err(format!("check failed for {}", failed.join(", ")).into());
+            }
+        },
+        Command::MockServer { .. } => unreachable!("mock-server is handled before loading the config"),
+        Command::Import { mode, path, env, project, on_conflict } => {
+            let env = env.unwrap_or_else(|| mode.clone());
+            let report = import_servers(&mut cfg, &env, &mode, project.as_deref(), &path, on_conflict)?;
+            save_config(&cfg)?;
+            for line in report.lines() {
+                println!("{}", line);
+            }
+            println!("{}", report.summary());
+        },
+    }
+    Ok(())
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+fn enabled_servers(cfg: &McpServersConfig, env: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let env_cfg = cfg.environments.get(env)
+        .ok_or_else(|| format!("unknown environment: {}", env))?;
+    Ok(env_cfg.enable.clone().unwrap_or_default())
+}
+
+/// listサブコマンドの出力を組み立てる（名前順）
+pub fn list_output(cfg: &McpServersConfig, env: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let mut servers: Vec<&String> = cfg.mcp_servers.keys().collect();
+    servers.sort();
+    let mut out = String::new();
+    match env {
+        Some(env) => {
+            let enabled = enabled_servers(cfg, env)?;
+            for server in servers {
+                let checked = if enabled.contains(server) { "[x] " } else { "[ ] " };
+                out.push_str(&format!("{}{}\n", checked, server));
+            }
+        },
+        None => {
+            let mut envs: Vec<(&String, &EnvironmentConfig)> = cfg.environments.iter().collect();
+            envs.sort_by_key(|(name, _)| *name);
+            out.push_str("Environments:\n");
+            for (name, env_cfg) in envs {
+                let mode = env_cfg.mode.as_deref().unwrap_or("-");
+                out.push_str(&format!("  {} ({}) {}\n", name, mode, env_cfg.config_path));
+            }
+            out.push_str("MCP Servers:\n");
+            for server in servers {
+                match &cfg.mcp_servers[server].description {
+                    Some(description) => out.push_str(&format!("  {} - {}\n", server, description)),
+                    None => out.push_str(&format!("  {}\n", server)),
+                }
+            }
+        },
+    }
+    Ok(out)
+}
+
+/// showサブコマンドの出力。serverを省略した場合は全サーバ（envがあればその環境で有効なサーバ）
+pub fn show_output(cfg: &McpServersConfig, server: Option<&str>, env: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let names: Vec<String> = match (server, env) {
+        (Some(server), _) => {
+            if !cfg.mcp_servers.contains_key(server) {
+                return Err(format!("unknown server: {}", server).into());
+            }
+            vec![server.to_string()]
+        },
+        (None, Some(env)) => enabled_servers(cfg, env)?,
+        (None, None) => cfg.mcp_servers.keys().cloned().collect(),
+    };
+    let servers = match env {
+        Some(env) => env_servers(cfg, env, &names)?,
+        None => enabled_server_defs(cfg, &names),
+    };
+    Ok(format!("{}\n", serde_json::to_string_pretty(&masked_servers_value(&servers)?)?))
+}
+
+/// sync --allの環境ごとの結果と、失敗した環境の一覧
+pub fn sync_all_output(results: &[EnvSyncResult]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut failed = Vec::new();
+    for (env, result) in results {
+        match result {
+            Ok(warnings) => {
+                out.push_str(&format!("{}: synced\n", env));
+                for warning in warnings {
+                    out.push_str(&format!("  warning: {}\n", warning));
+                }
+            },
+            Err(e) => {
+                out.push_str(&format!("{}: error: {}\n", env, e));
+                failed.push(env.clone());
+            },
+        }
+    }
+    (out, failed)
+}
+
+/// checkサブコマンドの出力と、失敗したサーバの一覧
+pub fn check_output(results: &[CheckResult]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut failed = Vec::new();
+    for (name, result) in results {
+        match result {
+            Ok(report) => out.push_str(&format!("{}: {}\n", name, report.summary())),
+            Err(e) => {
+                out.push_str(&format!("{}: error: {}\n", name, e));
+                failed.push(name.clone());
+            },
+        }
+    }
+    (out, failed)
+}
+
+/// statusサブコマンドの出力と、ずれがある（または確認できない）環境の一覧
+pub fn status_output(cfg: &McpServersConfig, env: Option<&str>) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
+    let envs: Vec<String> = match env {
+        Some(env) => {
+            enabled_servers(cfg, env)?;
+            vec![env.to_string()]
+        },
+        None => {
+            let mut envs: Vec<String> = cfg.environments.keys().cloned().collect();
+            envs.sort();
+            envs
+        },
+    };
+    let cache = RefCache::default();
+    let mut out = String::new();
+    let mut failed = Vec::new();
+    for env in envs {
+        match env_drift(cfg, &env, &cache) {
+            Ok(None) => out.push_str(&format!("{}: no target (mode is not set)\n", env)),
+            Ok(Some((plan, drift))) if drift.is_empty() => {
+                out.push_str(&format!("{}: in sync ({})\n", env, plan.path.display()));
+            },
+            Ok(Some((plan, drift))) => {
+                out.push_str(&format!("{}: drift ({})\n", env, plan.path.display()));
+                for line in drift.lines() {
+                    out.push_str(&format!("{}\n", line));
+                }
+                failed.push(env);
+            },
+            Err(e) => {
+                out.push_str(&format!("{}: error: {}\n", env, e));
+                failed.push(env);
+            },
+        }
+    }
+    Ok((out, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn sample_config() -> McpServersConfig {
+        let mut mcp_servers = HashMap::new();
+        for name in ["b", "a"] {
+            mcp_servers.insert(name.to_string(), McpServerConfig {
+                command: "echo".to_string(),
+                args: vec![],
+                env: HashMap::new(),
+                ..Default::default()
+            });
+        }
+        let mut environments = HashMap::new();
+        environments.insert("env1".to_string(), EnvironmentConfig {
+            config_path: "/tmp/test.json".to_string(),
+            enable: Some(vec!["b".to_string()]),
+            preset: None,
+            mode: Some("claude_desktop".to_string()),
+            ..Default::default()
+        });
+        McpServersConfig { mcp_servers, environments, ..Default::default() }
+    }
+
+    #[test]
+    fn test_list_output_env() {
+        let cfg = sample_config();
+        let out = list_output(&cfg, Some("env1")).unwrap();
+        assert_eq!(out, "[ ] a\n[x] b\n");
+        assert!(list_output(&cfg, Some("nope")).is_err());
+    }
+
+    #[test]
+    fn test_show_output_masks_secrets() {
+        unsafe { std::env::set_var("CLI_TEST_SHOW_TOKEN", "ghp_secret"); }
+        let mut cfg = sample_config();
+        cfg.mcp_servers.get_mut("b").unwrap().env = HashMap::from([
+            ("GITHUB_TOKEN".to_string(), "$CLI_TEST_SHOW_TOKEN".to_string()),
+            ("API_KEY".to_string(), "literal-secret".to_string()),
+        ]);
+        let out = show_output(&cfg, Some("b"), None).unwrap();
+        assert!(out.contains("$CLI_TEST_SHOW_TOKEN"));
+        assert!(!out.contains("literal-secret"));
+        let out = show_output(&cfg, None, Some("env1")).unwrap();
+        assert!(!out.contains("ghp_secret"));
+        assert!(!out.contains("\"a\""));
+        assert!(out.contains(MASK));
+        assert!(show_output(&cfg, Some("nope"), None).is_err());
+    }
+
+    #[test]
+    fn test_sync_all_output() {
+        let results = vec![
+            ("env1".to_string(), Ok(vec!["plaintext".to_string()])),
+            ("env2".to_string(), Err("unknown mode: x".into())),
+            ("env3".to_string(), Ok(vec![])),
+        ];
+        let (out, failed) = sync_all_output(&results);
+        assert_eq!(out, "env1: synced\n  warning: plaintext\nenv2: error: unknown mode: x\nenv3: synced\n");
+        assert_eq!(failed, vec!["env2"]);
+    }
+
+    #[test]
+    fn test_status_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let mut cfg = sample_config();
+        cfg.environments.get_mut("env1").unwrap().config_path = path.display().to_string();
+        cfg.environments.insert("plain".to_string(), EnvironmentConfig::default());
+        let (out, failed) = status_output(&cfg, None).unwrap();
+        assert_eq!(out, format!("env1: drift ({})\n  missing: b\nplain: no target (mode is not set)\n", path.display()));
+        assert_eq!(failed, vec!["env1"]);
+        std::fs::write(&path, r#"{"mcpServers": {"b": {"command": "echo", "args": [], "env": {}}}}"#).unwrap();
+        let (out, failed) = status_output(&cfg, Some("env1")).unwrap();
+        assert_eq!(out, format!("env1: in sync ({})\n", path.display()));
+        assert!(failed.is_empty());
+        assert!(status_output(&cfg, Some("nope")).is_err());
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        let cli = Cli::try_parse_from(["mcpallete", "enable", "env1", "a", "b"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Enable { ref servers, .. }) if servers.len() == 2));
+        let cli = Cli::try_parse_from(["mcpallete"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(Cli::try_parse_from(["mcpallete", "disable", "env1"]).is_err());
+        let cli = Cli::try_parse_from(["mcpallete", "rollback", "env1"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Rollback { n: 1, .. })));
+        let cli = Cli::try_parse_from(["mcpallete", "sync", "env1", "--dry-run"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Sync { dry_run: true, .. })));
+        let cli = Cli::try_parse_from(["mcpallete", "sync", "--all"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Sync { all: true, env: None, .. })));
+        assert!(Cli::try_parse_from(["mcpallete", "sync"]).is_err());
+        let cli = Cli::try_parse_from(["mcpallete", "check", "--timeout", "500"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Check { server: None, timeout: 500, .. })));
+        assert!(Cli::try_parse_from(["mcpallete", "sync", "env1", "--all"]).is_err());
+        let cli = Cli::try_parse_from(["mcpallete", "import", "cursor", "mcp.json", "--on-conflict", "rename"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Import { on_conflict: Conflict::Rename, env: None, .. })));
+    }
+}
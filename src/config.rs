@@ -1,7 +1,13 @@
+use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use regex::Regex;
+use crate::fsutil::*;
 use crate::model::*;
 
 pub fn ensure_config() -> Result<(), Box<dyn std::error::Error>> {
@@ -42,12 +48,324 @@ pub fn load_config() -> Result<McpServersConfig, Box<dyn std::error::Error>> {
     Ok(cfg)
 }
 
+pub fn save_config(cfg: &McpServersConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let path = get_config_file_path();
+    let json = serde_json::to_string_pretty(cfg)?;
+    write_with_backup(&path, &json, Some(&get_backup_dir()))
+}
+
+/// 書き換える前のファイルのバックアップ先
+pub fn get_backup_dir() -> PathBuf {
+    let config_path = get_config_file_path();
+    config_path.parent().unwrap_or(Path::new(".")).join("backups")
+}
+
+/// 環境ごとの適用履歴の保存先
+pub fn get_history_dir() -> PathBuf {
+    let config_path = get_config_file_path();
+    config_path.parent().unwrap_or(Path::new(".")).join("history")
+}
+
 pub fn expand_env_vars(s: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let re = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)|\$\{([A-Za-z_][A-Za-z0-9_]*)\}")?;
-    Ok(re.replace_all(s, |caps: &regex::Captures| {
-        let var = caps.get(1).or_else(|| caps.get(2)).map(|m| m.as_str()).unwrap_or("");
-        std::env::var(var).unwrap_or_else(|_| "".to_string())
-    }).to_string())
+    VarContext::default().expand(s)
+}
+
+/// ${cmd:...}の既定のタイムアウト
+const CMD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ${cmd:...} / ${file:...} の結果のキャッシュ（参照の文字列ごと）
+/// 1回の実行の中で複数の環境のVarContextに共有させ、同じコマンドを何度も実行しないようにする
+pub type RefCache = Arc<Mutex<HashMap<String, String>>>;
+
+/// 変数の解決元。プロセスの環境変数を優先し、無い場合だけenvFilesから読んだ値を使う
+/// ${cmd:...} / ${file:...} の結果はcacheを共有するコンテキストの間でキャッシュする
+#[derive(Debug)]
+pub struct VarContext {
+    file_vars: HashMap<String, String>,
+    cache: RefCache,
+    pub timeout: Duration,
+}
+
+impl Default for VarContext {
+    fn default() -> Self {
+        VarContext { file_vars: HashMap::new(), cache: RefCache::default(), timeout: CMD_TIMEOUT }
+    }
+}
+
+impl VarContext {
+    /// envFilesを順に読み込む。同じ変数は後のファイルの値で上書きする
+    /// パスは環境変数展開し、相対パスはbasic_config.jsonのディレクトリを基準にする
+    pub fn from_env_files<'a>(paths: impl IntoIterator<Item = &'a String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let config_path = get_config_file_path();
+        let base = config_path.parent().unwrap_or(Path::new("."));
+        let mut file_vars = HashMap::new();
+        for path in paths {
+            let path = base.join(expand_env_vars(path)?);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read env file {}: {}", path.display(), e))?;
+            file_vars.extend(parse_env_file(&content));
+        }
+        Ok(VarContext { file_vars, ..Default::default() })
+    }
+
+    /// ${cmd:...} / ${file:...} の結果を他のコンテキストと共有する
+    pub fn with_cache(mut self, cache: &RefCache) -> Self {
+        self.cache = Arc::clone(cache);
+        self
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<String> {
+        env::var(name).ok().or_else(|| self.file_vars.get(name).cloned())
+    }
+
+    /// 1つの文字列を展開する。${VAR:?}が未設定の場合はエラー
+    pub fn expand(&self, s: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mut missing = Vec::new();
+        let out = self.resolve(s, &mut missing)?;
+        match missing.first() {
+            Some(m) => Err(m.to_string().into()),
+            None => Ok(out),
+        }
+    }
+
+    /// 参照を解決する。未設定（:-と:?では空文字列も含む）の場合は既定値を使い、
+    /// :?付きの変数はmissingに追加する
+    pub fn resolve(&self, s: &str, missing: &mut Vec<MissingVar>) -> Result<String, Box<dyn std::error::Error>> {
+        replace_var_refs(s, |r| {
+            if r.source != VarSource::Env {
+                return self.resolve_external(r);
+            }
+            let has_fallback = r.default.is_some() || r.required.is_some();
+            match self.lookup(r.name).filter(|v| !has_fallback || !v.is_empty()) {
+                Some(v) => Ok(v),
+                None => {
+                    if let Some(message) = r.required {
+                        missing.push(MissingVar { name: r.name.to_string(), message: message.to_string() });
+                    }
+                    Ok(r.default.unwrap_or("").to_string())
+                },
+            }
+        })
+    }
+
+    /// 環境変数の参照を${VAR}（既定値があれば${VAR:-default}）の形で残し、
+    /// クライアントが解決できない${cmd:...} / ${file:...}だけを解決する
+    /// ${VAR:?}の変数が未設定（空文字列を含む）の場合はmissingに追加する
+    pub fn keep_refs(&self, s: &str, missing: &mut Vec<MissingVar>) -> Result<String, Box<dyn std::error::Error>> {
+        replace_var_refs(s, |r| match (r.source, r.default) {
+            (VarSource::Env, Some(default)) => Ok(format!("${{{}:-{}}}", r.name, default)),
+            (VarSource::Env, None) => {
+                self.check_required(r, missing);
+                Ok(format!("${{{}}}", r.name))
+            },
+            _ => self.resolve_external(r),
+        })
+    }
+
+    /// ${cmd:...} / ${file:...}だけを解決し、環境変数の参照と$$は書かれたまま残す
+    /// ${VAR:?}の変数が未設定（空文字列を含む）の場合はmissingに追加する
+    pub fn resolve_external_refs(&self, s: &str, missing: &mut Vec<MissingVar>) -> Result<String, Box<dyn std::error::Error>> {
+        replace_refs(s, true, |r| {
+            if r.source != VarSource::Env {
+                return self.resolve_external(r);
+            }
+            self.check_required(r, missing);
+            Ok(r.text.to_string())
+        })
+    }
+
+    /// 参照を残す場合でも${VAR:?}の変数は設定されている必要がある
+    fn check_required(&self, r: &VarRef, missing: &mut Vec<MissingVar>) {
+        if let Some(message) = r.required
+            && self.lookup(r.name).is_none_or(|v| v.is_empty()) {
+            missing.push(MissingVar { name: r.name.to_string(), message: message.to_string() });
+        }
+    }
+
+    fn resolve_external(&self, r: &VarRef) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(value) = self.cache.lock().ok().and_then(|cache| cache.get(r.text).cloned()) {
+            return Ok(value);
+        }
+        let value = match r.source {
+            VarSource::Cmd => run_secret_command(r.name, self.timeout)?,
+            _ => read_secret_file(r.name)?,
+        };
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(r.text.to_string(), value.clone());
+        }
+        Ok(value)
+    }
+}
+
+/// シェルでコマンドを実行し、標準出力（末尾の改行を除く）を返す。timeoutを過ぎたら終了させる
+fn run_secret_command(cmd: &str, timeout: Duration) -> Result<String, Box<dyn std::error::Error>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run `{}`: {}", cmd, e))?;
+    // パイプが詰まって終了できなくならないよう、出力は終了を待つ間も別スレッドで読み続ける
+    let read_pipe = |pipe: Option<Box<dyn Read + Send>>| std::thread::spawn(move || {
+        let mut out = String::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_string(&mut out);
+        }
+        out
+    });
+    let stdout = read_pipe(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = read_pipe(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("`{}` timed out after {}ms", cmd, timeout.as_millis()).into());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        return Err(format!("`{}` failed ({}): {}", cmd, status, stderr.trim()).into());
+    }
+    Ok(stdout.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// ファイルの内容（末尾の改行を除く）を返す。~/はHOMEに置き換える
+fn read_secret_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(env::var("HOME")?).join(rest),
+        None => PathBuf::from(path),
+    };
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    Ok(content.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// .env形式（KEY=VALUE、export、#コメント、クォート）を読み込む。値の中の変数参照は展開しない
+pub fn parse_env_file(content: &str) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            inner.replace("\\n", "\n").replace("\\\"", "\"")
+        } else if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            inner.to_string()
+        } else {
+            // クォートしていない値は" #"以降をコメントとして扱う
+            value.split(" #").next().unwrap_or("").trim_end().to_string()
+        };
+        vars.insert(key.trim().to_string(), value);
+    }
+    vars
+}
+
+/// 参照の解決方法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VarSource {
+    Env,
+    Cmd,
+    File,
+}
+
+/// 変数参照。${VAR:-default}の既定値と${VAR:?message}のメッセージを持つ
+/// ${cmd:...} / ${file:...}ではnameがコマンドやパスになる
+pub struct VarRef<'a> {
+    pub source: VarSource,
+    pub name: &'a str,
+    pub default: Option<&'a str>,
+    pub required: Option<&'a str>,
+    /// 参照の元の文字列
+    pub text: &'a str,
+}
+
+/// ${VAR:?message}で要求されたが未設定だった変数
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingVar {
+    pub name: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for MissingVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{} is not set", self.name)
+        } else {
+            write!(f, "{}: {}", self.name, self.message)
+        }
+    }
+}
+
+/// $VAR / ${VAR} / ${VAR:-default} / ${VAR:?message} / ${cmd:...} / ${file:...} の参照を
+/// クロージャの結果で置き換える。$$は$になる
+pub fn replace_var_refs(s: &str, f: impl FnMut(&VarRef) -> Result<String, Box<dyn std::error::Error>>) -> Result<String, Box<dyn std::error::Error>> {
+    replace_refs(s, false, f)
+}
+
+/// replace_var_refsの本体。keep_escapesの場合は$$を$$のまま残す
+fn replace_refs(s: &str, keep_escapes: bool, mut f: impl FnMut(&VarRef) -> Result<String, Box<dyn std::error::Error>>) -> Result<String, Box<dyn std::error::Error>> {
+    let re = Regex::new(r"\$\$|\$([A-Za-z_][A-Za-z0-9_]*)|\$\{(?:(cmd|file):([^}]*)|([A-Za-z_][A-Za-z0-9_]*)(?:(:-|:\?)([^}]*))?)\}")?;
+    let mut out = String::with_capacity(s.len());
+    let mut last = 0;
+    for caps in re.captures_iter(s) {
+        let Some(m) = caps.get(0) else { continue };
+        out.push_str(&s[last..m.start()]);
+        last = m.end();
+        if m.as_str() == "$$" {
+            out.push_str(if keep_escapes { "$$" } else { "$" });
+            continue;
+        }
+        let var_ref = match caps.get(2).map(|m| m.as_str()) {
+            Some(source) => VarRef {
+                source: if source == "cmd" { VarSource::Cmd } else { VarSource::File },
+                name: caps.get(3).map(|m| m.as_str()).unwrap_or(""),
+                default: None,
+                required: None,
+                text: m.as_str(),
+            },
+            None => {
+                let arg = caps.get(6).map(|m| m.as_str()).unwrap_or("");
+                let (default, required) = match caps.get(5).map(|m| m.as_str()) {
+                    Some(":-") => (Some(arg), None),
+                    Some(":?") => (None, Some(arg)),
+                    _ => (None, None),
+                };
+                VarRef {
+                    source: VarSource::Env,
+                    name: caps.get(1).or_else(|| caps.get(4)).map(|m| m.as_str()).unwrap_or(""),
+                    default,
+                    required,
+                    text: m.as_str(),
+                }
+            },
+        };
+        out.push_str(&f(&var_ref)?);
+    }
+    out.push_str(&s[last..]);
+    Ok(out)
+}
+
+/// 環境変数の参照を、変数名と${VAR:-default}の既定値を受け取るクロージャの結果で置き換える
+/// ${cmd:...} / ${file:...}はそのまま残す
+pub fn replace_env_vars(s: &str, mut f: impl FnMut(&str, Option<&str>) -> String) -> Result<String, Box<dyn std::error::Error>> {
+    replace_var_refs(s, |r| match r.source {
+        VarSource::Env => Ok(f(r.name, r.default)),
+        _ => Ok(r.text.to_string()),
+    })
 }
 
 #[cfg(test)]
@@ -78,6 +396,104 @@ mod tests {
         assert_eq!(expanded, "Value: !");
     }
 
+    #[test]
+    fn test_expand_env_vars_default_and_escape() {
+        unsafe { env::set_var("TEST_EXPAND3", "set"); }
+        assert_eq!(expand_env_vars("${NOT_SET:-fallback}").unwrap(), "fallback");
+        assert_eq!(expand_env_vars("${TEST_EXPAND3:-fallback}").unwrap(), "set");
+        assert_eq!(expand_env_vars("$$HOME costs $$5").unwrap(), "$HOME costs $5");
+    }
+
+    #[test]
+    fn test_expand_env_vars_required() {
+        let err = expand_env_vars("${NOT_SET:?set an API key}").unwrap_err();
+        assert_eq!(err.to_string(), "NOT_SET: set an API key");
+        let mut missing = Vec::new();
+        let out = VarContext::default().resolve("${RESOLVE_TEST_A:?}-${RESOLVE_TEST_B}", &mut missing).unwrap();
+        assert_eq!(out, "-");
+        assert_eq!(missing, vec![MissingVar { name: "RESOLVE_TEST_A".to_string(), message: String::new() }]);
+        assert_eq!(missing[0].to_string(), "RESOLVE_TEST_A is not set");
+    }
+
+    #[test]
+    fn test_resolve_cmd_and_file_refs() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let secret = tmp_dir.path().join("secret");
+        fs::write(&secret, "from-file\n").unwrap();
+        // 実行回数を数えるスクリプト
+        let counter = tmp_dir.path().join("count");
+        let script = tmp_dir.path().join("secret.sh");
+        fs::write(&script, format!("#!/bin/sh\necho x >> {}\necho from-cmd\n", counter.display())).unwrap();
+        let ctx = VarContext::default();
+        let input = format!("${{cmd:sh {}}}/${{file:{}}}", script.display(), secret.display());
+        assert_eq!(ctx.expand(&input).unwrap(), "from-cmd/from-file");
+        assert_eq!(ctx.expand(&input).unwrap(), "from-cmd/from-file");
+        assert_eq!(fs::read_to_string(&counter).unwrap().lines().count(), 1);
+        // cacheを共有するコンテキストでは実行しない
+        let cache = RefCache::default();
+        VarContext::default().with_cache(&cache).expand(&input).unwrap();
+        VarContext::default().with_cache(&cache).expand(&input).unwrap();
+        assert_eq!(fs::read_to_string(&counter).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_keep_refs() {
+        unsafe { env::set_var("KEEP_REFS_TEST_SET", "v"); }
+        let ctx = VarContext::default();
+        let mut missing = Vec::new();
+        assert_eq!(ctx.keep_refs("Bearer $TOKEN ${A:-x} ${KEEP_REFS_TEST_SET:?msg} $$", &mut missing).unwrap(), "Bearer ${TOKEN} ${A:-x} ${KEEP_REFS_TEST_SET} $");
+        assert!(missing.is_empty());
+        assert_eq!(ctx.keep_refs("${cmd:echo s3cret}", &mut missing).unwrap(), "s3cret");
+        // 未設定の${VAR:?}は参照を残す場合もエラーにする
+        ctx.keep_refs("${KEEP_REFS_TEST_UNSET:?needed}", &mut missing).unwrap();
+        assert_eq!(missing[0].to_string(), "KEEP_REFS_TEST_UNSET: needed");
+    }
+
+    #[test]
+    fn test_resolve_cmd_errors() {
+        let mut ctx = VarContext::default();
+        let err = ctx.expand("${cmd:echo oops >&2; exit 3}").unwrap_err().to_string();
+        assert!(err.contains("failed") && err.contains("oops"), "{}", err);
+        ctx.timeout = Duration::from_millis(100);
+        let err = ctx.expand("${cmd:sleep 5}").unwrap_err().to_string();
+        assert!(err.contains("timed out"), "{}", err);
+        assert!(ctx.expand("${file:/nonexistent/secret}").is_err());
+        // パイプのバッファより大きい出力でも終了を待てる
+        ctx.timeout = Duration::from_secs(5);
+        assert_eq!(ctx.expand("${cmd:head -c 200000 /dev/zero | tr '\\0' a}").unwrap().len(), 200000);
+        assert_eq!(replace_env_vars("$A ${cmd:pass show x}", |v, _| format!("<{}>", v)).unwrap(), "<A> ${cmd:pass show x}");
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let content = "# keys\nexport A=1\nB = \"two words\"\nC='$NOT_EXPANDED'\nD=x # comment\nE=\"line\\nnext\"\ninvalid\n";
+        let vars = parse_env_file(content);
+        assert_eq!(vars["A"], "1");
+        assert_eq!(vars["B"], "two words");
+        assert_eq!(vars["C"], "$NOT_EXPANDED");
+        assert_eq!(vars["D"], "x");
+        assert_eq!(vars["E"], "line\nnext");
+        assert_eq!(vars.len(), 5);
+    }
+
+    #[test]
+    fn test_var_context_precedence() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let first = tmp_dir.path().join("first.env");
+        let second = tmp_dir.path().join("second.env");
+        fs::write(&first, "CTX_TEST_A=first\nCTX_TEST_B=first\nCTX_TEST_PROCESS=file\n").unwrap();
+        fs::write(&second, "CTX_TEST_B=second\n").unwrap();
+        unsafe { env::set_var("CTX_TEST_PROCESS", "process"); }
+        let paths = [first.to_str().unwrap().to_string(), second.to_str().unwrap().to_string()];
+        let ctx = VarContext::from_env_files(&paths).unwrap();
+        assert_eq!(ctx.lookup("CTX_TEST_A").as_deref(), Some("first"));
+        assert_eq!(ctx.lookup("CTX_TEST_B").as_deref(), Some("second"));
+        assert_eq!(ctx.lookup("CTX_TEST_PROCESS").as_deref(), Some("process"));
+        assert_eq!(ctx.expand("${CTX_TEST_A}-${CTX_TEST_UNSET:-none}").unwrap(), "first-none");
+        let missing = [tmp_dir.path().join("none.env").to_str().unwrap().to_string()];
+        assert!(VarContext::from_env_files(&missing).unwrap_err().to_string().contains("none.env"));
+    }
+
     #[test]
     fn test_ensure_config_and_load_config() {
         let tmp_dir = tempfile::tempdir().unwrap();
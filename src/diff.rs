@@ -0,0 +1,271 @@
+use std::collections::{BTreeSet, HashMap};
+use crate::config::*;
+use crate::model::*;
+use crate::secret::*;
+use crate::sync::*;
+
+// 書き出し前後のサーバ定義の差分表示（シークレットは伏せる）
+
+fn opt_str<T: ToString>(v: &Option<T>) -> String {
+    v.as_ref().map(|v| v.to_string()).unwrap_or_else(|| "(none)".to_string())
+}
+
+/// env・headersのキーごとの差分
+fn map_changes(field: &str, before: &HashMap<String, String>, after: &HashMap<String, String>, secret_env: &[String]) -> Vec<String> {
+    let show = |key: &str, value: &str| if is_secret_key(key, secret_env) { mask_value(value) } else { value.to_string() };
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    keys.into_iter().filter_map(|key| match (before.get(key), after.get(key)) {
+        (None, Some(new)) => Some(format!("+ {}.{} = {}", field, key, show(key, new))),
+        (Some(_), None) => Some(format!("- {}.{}", field, key)),
+        (Some(old), Some(new)) if old != new => Some(format!("~ {}.{}: {} -> {}", field, key, show(key, old), show(key, new))),
+        _ => None,
+    }).collect()
+}
+
+/// 変更されたフィールドの一覧
+fn field_changes(before: &McpServerConfig, after: &McpServerConfig) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut scalar = |field: &str, old: String, new: String| {
+        if old != new {
+            lines.push(format!("~ {}: {} -> {}", field, old, new));
+        }
+    };
+    scalar("transport", before.transport.as_str().to_string(), after.transport.as_str().to_string());
+    scalar("command", before.command.clone(), after.command.clone());
+    scalar("args", format!("{:?}", before.args), format!("{:?}", after.args));
+    scalar("url", opt_str(&before.url), opt_str(&after.url));
+    scalar("cwd", opt_str(&before.cwd), opt_str(&after.cwd));
+    scalar("timeout", opt_str(&before.timeout), opt_str(&after.timeout));
+    scalar("trust", opt_str(&before.trust), opt_str(&after.trust));
+    lines.extend(map_changes("env", &before.env, &after.env, &after.secret_env));
+    lines.extend(map_changes("headers", &before.headers, &after.headers, &after.secret_env));
+    let keys: BTreeSet<&String> = before.extra.keys().chain(after.extra.keys()).collect();
+    for key in keys {
+        match (before.extra.get(key), after.extra.get(key)) {
+            (None, Some(new)) => lines.push(format!("+ {} = {}", key, new)),
+            (Some(_), None) => lines.push(format!("- {}", key)),
+            (Some(old), Some(new)) if old != new => lines.push(format!("~ {}: {} -> {}", key, old, new)),
+            _ => {},
+        }
+    }
+    lines
+}
+
+/// 追加・削除・変更されたサーバの差分。変更・追加されたサーバは字下げしてフィールドを示す
+pub fn diff_servers(current: &HashMap<String, McpServerConfig>, next: &HashMap<String, McpServerConfig>) -> Vec<String> {
+    let names: BTreeSet<&String> = current.keys().chain(next.keys()).collect();
+    let mut lines = Vec::new();
+    for name in names {
+        let (marker, changes) = match (current.get(name), next.get(name)) {
+            (None, Some(new)) => ("+", field_changes(&McpServerConfig { secret_env: new.secret_env.clone(), ..Default::default() }, new)),
+            (Some(_), None) => ("-", vec![]),
+            (Some(old), Some(new)) if old != new => ("~", field_changes(old, new)),
+            _ => continue,
+        };
+        lines.push(format!("{} {}", marker, name));
+        lines.extend(changes.into_iter().map(|l| format!("    {}", l)));
+    }
+    lines
+}
+
+/// 書き出し先・差分・警告をまとめたプレビュー
+pub fn preview_lines(plan: &TargetPlan) -> Vec<String> {
+    let mut lines = vec![plan.path.display().to_string()];
+    let diff = diff_servers(&plan.current, &plan.next);
+    if diff.is_empty() {
+        lines.push("(no server changes)".to_string());
+    }
+    lines.extend(diff);
+    lines.extend(plan.warnings.iter().map(|w| format!("warning: {}", w)));
+    lines
+}
+
+/// 全環境を保存済みのenableで同期した場合のプレビュー（環境名順）
+/// 確認後にsync_allへ同じcacheを渡すと、プレビューと同じ値を書き出す
+pub fn preview_all_lines(cfg: &McpServersConfig, cache: &RefCache) -> Vec<String> {
+    let mut envs: Vec<&String> = cfg.environments.keys().collect();
+    envs.sort();
+    let mut lines = Vec::new();
+    for env_name in envs {
+        let enabled = cfg.environments[env_name].enable.clone().unwrap_or_default();
+        lines.push(format!("[{}]", env_name));
+        match plan_environment(cfg, env_name, &enabled, cache) {
+            Ok(Some(plan)) => lines.extend(preview_lines(&plan)),
+            Ok(None) => lines.push("(no target)".to_string()),
+            Err(e) => lines.push(format!("error: {}", e)),
+        }
+    }
+    lines
+}
+
+/// ターゲットファイルとenableのずれ
+#[derive(Debug, Default, PartialEq)]
+pub struct Drift {
+    /// ターゲットにあるがenableに無いサーバ
+    pub unexpected: Vec<String>,
+    /// enableにあるがターゲットに無いサーバ
+    pub missing: Vec<String>,
+    /// 定義が異なるサーバと、設定上の値 -> ターゲットの値のフィールド差分
+    pub changed: Vec<(String, Vec<String>)>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.unexpected.is_empty() && self.missing.is_empty() && self.changed.is_empty()
+    }
+
+    /// 字下げしたずれの一覧
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !self.unexpected.is_empty() {
+            lines.push(format!("  not enabled: {}", self.unexpected.join(", ")));
+        }
+        if !self.missing.is_empty() {
+            lines.push(format!("  missing: {}", self.missing.join(", ")));
+        }
+        for (name, changes) in &self.changed {
+            lines.push(format!("  changed: {}", name));
+            lines.extend(changes.iter().map(|l| format!("      {}", l)));
+        }
+        lines
+    }
+}
+
+/// 現在のターゲットファイル（plan.current）と、同期した場合の内容（plan.next）のずれ
+pub fn drift(plan: &TargetPlan) -> Drift {
+    let names: BTreeSet<&String> = plan.current.keys().chain(plan.next.keys()).collect();
+    let mut drift = Drift::default();
+    for name in names {
+        match (plan.next.get(name), plan.current.get(name)) {
+            (None, Some(_)) => drift.unexpected.push(name.clone()),
+            (Some(_), None) => drift.missing.push(name.clone()),
+            (Some(expected), Some(actual)) if expected != actual => {
+                drift.changed.push((name.clone(), field_changes(expected, actual)));
+            },
+            _ => {},
+        }
+    }
+    drift
+}
+
+/// 環境のenableとターゲットファイルのずれ。ターゲットを持たない環境はNone
+pub fn env_drift(cfg: &McpServersConfig, env_name: &str, cache: &RefCache) -> Result<Option<(TargetPlan, Drift)>, Box<dyn std::error::Error>> {
+    let enabled = cfg.environments.get(env_name)
+        .ok_or_else(|| format!("unknown environment: {}", env_name))?
+        .enable.clone().unwrap_or_default();
+    Ok(plan_environment(cfg, env_name, &enabled, cache)?.map(|plan| {
+        let drift = drift(&plan);
+        (plan, drift)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(args: &[&str], env: &[(&str, &str)]) -> McpServerConfig {
+        McpServerConfig {
+            command: "npx".to_string(),
+            args: args.iter().map(|s| s.to_string()).collect(),
+            env: env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_servers() {
+        let current = HashMap::from([
+            ("keep".to_string(), server(&["a"], &[])),
+            ("gone".to_string(), server(&[], &[])),
+            ("edit".to_string(), server(&["a"], &[("API_KEY", "old-secret"), ("MODE", "dev"), ("OLD", "x")])),
+        ]);
+        let next = HashMap::from([
+            ("keep".to_string(), server(&["a"], &[])),
+            ("new".to_string(), server(&["-y"], &[("GITHUB_TOKEN", "ghp_new")])),
+            ("edit".to_string(), server(&["b"], &[("API_KEY", "new-secret"), ("MODE", "prod")])),
+        ]);
+        let lines = diff_servers(&current, &next);
+        assert_eq!(lines, vec![
+            "~ edit",
+            "    ~ args: [\"a\"] -> [\"b\"]",
+            &format!("    ~ env.API_KEY: {} -> {}", MASK, MASK),
+            "    ~ env.MODE: dev -> prod",
+            "    - env.OLD",
+            "- gone",
+            "+ new",
+            "    ~ command:  -> npx",
+            "    ~ args: [] -> [\"-y\"]",
+            &format!("    + env.GITHUB_TOKEN = {}", MASK),
+        ]);
+        assert!(diff_servers(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_env_drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("claude_desktop_config.json");
+        let cfg = McpServersConfig {
+            mcp_servers: HashMap::from([
+                ("a".to_string(), server(&["a"], &[])),
+                ("b".to_string(), server(&["b"], &[])),
+                ("c".to_string(), server(&["c"], &[])),
+            ]),
+            environments: HashMap::from([("env1".to_string(), EnvironmentConfig {
+                config_path: path.display().to_string(),
+                enable: Some(vec!["a".to_string(), "b".to_string()]),
+                mode: Some("claude_desktop".to_string()),
+                ..Default::default()
+            })]),
+            ..Default::default()
+        };
+        // ファイルが無い場合は全て足りない
+        let (_, found) = env_drift(&cfg, "env1", &RefCache::default()).unwrap().unwrap();
+        assert_eq!(found.missing, vec!["a", "b"]);
+
+        std::fs::write(&path, r#"{"mcpServers": {
+            "a": {"command": "npx", "args": ["a"], "env": {}},
+            "b": {"command": "npx", "args": ["edited"], "env": {}},
+            "c": {"command": "npx", "args": ["c"], "env": {}}
+        }}"#).unwrap();
+        let (_, found) = env_drift(&cfg, "env1", &RefCache::default()).unwrap().unwrap();
+        assert_eq!(found, Drift {
+            unexpected: vec!["c".to_string()],
+            missing: vec![],
+            changed: vec![("b".to_string(), vec!["~ args: [\"b\"] -> [\"edited\"]".to_string()])],
+        });
+        assert_eq!(found.lines()[0], "  not enabled: c");
+
+        let written = write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["a".to_string(), "b".to_string()]), &Default::default(), None);
+        assert!(written.is_ok());
+        let (_, found) = env_drift(&cfg, "env1", &RefCache::default()).unwrap().unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn test_preview_all_resolves_commands_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let counter = dir.path().join("count");
+        let command = format!("${{cmd:echo x >> {}; echo t0ken}}", counter.display());
+        let env = |name: &str| EnvironmentConfig {
+            config_path: dir.path().join(name).display().to_string(),
+            enable: Some(vec!["a".to_string()]),
+            mode: Some("claude_desktop".to_string()),
+            ..Default::default()
+        };
+        let cfg = McpServersConfig {
+            mcp_servers: HashMap::from([("a".to_string(), server(&[], &[("TOKEN", &command)]))]),
+            environments: HashMap::from([("env1".to_string(), env("one.json")), ("env2".to_string(), env("two.json"))]),
+            ..Default::default()
+        };
+        let cache = RefCache::default();
+        let lines = preview_all_lines(&cfg, &cache);
+        assert!(!lines.iter().any(|l| l.starts_with("error")), "{:?}", lines);
+        // 環境をまたいでも同じ参照は1回だけ解決する
+        assert_eq!(std::fs::read_to_string(&counter).unwrap().lines().count(), 1);
+        // 確認後の書き込みでもプレビューで解決した値を使う
+        let env_cfg = &cfg.environments["env1"];
+        let ctx = env_context(&cfg, env_cfg, &cache).unwrap();
+        write_target(env_cfg, enabled_server_defs(&cfg, &["a".to_string()]), &ctx, None).unwrap();
+        assert_eq!(std::fs::read_to_string(&counter).unwrap().lines().count(), 1);
+    }
+}
@@ -0,0 +1,213 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// ファイル書き込みの共通処理（アトミックな書き込みとバックアップ）
+
+/// 1ファイルあたりに残すバックアップの数
+pub const MAX_BACKUPS: usize = 20;
+
+/// シンボリックリンクをたどった実際の書き込み先（dotfilesへのリンクを通常ファイルで置き換えないように）
+/// リンク先がまだ無い場合もリンクが指すパスを返す
+pub fn resolve_target(path: &Path) -> PathBuf {
+    if let Ok(real) = std::fs::canonicalize(path) {
+        return real;
+    }
+    match std::fs::read_link(path) {
+        Ok(link) => path.parent().unwrap_or(Path::new(".")).join(link),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// 同じディレクトリの一時ファイルに書いてからrenameで置き換える
+/// pathがシンボリックリンクならリンク先を書き換える。既存ファイルがあればパーミッションを引き継ぐ
+pub fn atomic_write(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    let path = &resolve_target(path);
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    let file_name = path.file_name().ok_or_else(|| format!("invalid path: {}", path.display()))?;
+    let tmp = dir.join(format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id()));
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = std::fs::File::create(&tmp)?;
+        // シークレットを書き込む前にパーミッションを揃える
+        if let Ok(meta) = std::fs::metadata(path) {
+            file.set_permissions(meta.permissions())?;
+        }
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// バックアップファイル名の接頭辞（元のパスの区切りを%に置き換えたもの）
+fn backup_prefix(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let key: String = path.to_string_lossy()
+        .trim_start_matches(['/', '\\'])
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':') { '%' } else { c })
+        .collect();
+    format!("{}.", key)
+}
+
+/// pathのバックアップを古い順に返す
+pub fn list_backups(path: &Path, backup_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let prefix = backup_prefix(path);
+    let entries = match std::fs::read_dir(backup_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().strip_prefix(&prefix).is_some_and(is_timestamp))
+        .map(|e| e.path())
+        .collect();
+    backups.sort();
+    Ok(backups)
+}
+
+/// 既存のpathをbackup_dirにタイムスタンプ付きでコピーし、古いものはMAX_BACKUPSまで削除する
+/// pathが存在しない場合は何もしない
+pub fn backup_file(path: &Path, backup_dir: &Path) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    std::fs::create_dir_all(backup_dir)?;
+    let backup = backup_dir.join(format!("{}{}", backup_prefix(path), timestamp_now()));
+    std::fs::copy(path, &backup)?;
+    let backups = list_backups(path, backup_dir)?;
+    for old in backups.iter().take(backups.len().saturating_sub(MAX_BACKUPS)) {
+        std::fs::remove_file(old)?;
+    }
+    Ok(Some(backup))
+}
+
+/// 内容が変わる場合だけ、バックアップを取ってからアトミックに書き込む
+pub fn write_with_backup(path: &Path, content: &str, backup_dir: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = &resolve_target(path);
+    if std::fs::read_to_string(path).is_ok_and(|old| old == content) {
+        return Ok(());
+    }
+    if let Some(backup_dir) = backup_dir {
+        backup_file(path, backup_dir)?;
+    }
+    atomic_write(path, content)
+}
+
+/// 現在時刻のタイムスタンプ（UTC、"YYYYMMDDTHHMMSS.mmmZ"）
+pub fn timestamp_now() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format_timestamp(now.as_secs(), now.subsec_millis())
+}
+
+/// UNIX時刻をUTCのタイムスタンプにする（辞書順と時刻順が一致する）
+pub fn format_timestamp(secs: u64, millis: u32) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (y, m, d) = civil_from_days(days);
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}.{:03}Z", y, m, d, rem / 3600, rem % 3600 / 60, rem % 60, millis)
+}
+
+/// タイムスタンプを表示用の"YYYY-MM-DD HH:MM:SS UTC"にする
+pub fn display_timestamp(ts: &str) -> String {
+    if !is_timestamp(ts) {
+        return ts.to_string();
+    }
+    format!("{}-{}-{} {}:{}:{} UTC", &ts[0..4], &ts[4..6], &ts[6..8], &ts[9..11], &ts[11..13], &ts[13..15])
+}
+
+fn is_timestamp(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 20 && b[8] == b'T' && b[15] == b'.' && b[19] == b'Z'
+        && b.iter().enumerate().all(|(i, c)| matches!(i, 8 | 15 | 19) || c.is_ascii_digit())
+}
+
+/// 1970-01-01からの日数を年月日にする
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0, 0), "19700101T000000.000Z");
+        assert_eq!(format_timestamp(951782400 + 3661, 42), "20000229T010101.042Z");
+        assert_eq!(format_timestamp(1792108800, 999), "20261016T000000.999Z");
+        assert!(is_timestamp(&timestamp_now()));
+        assert_eq!(display_timestamp("20261016T093005.120Z"), "2026-10-16 09:30:05 UTC");
+        assert_eq!(display_timestamp("bogus"), "bogus");
+    }
+
+    #[test]
+    fn test_atomic_write_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("a/b.json");
+        atomic_write(&path, "one").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        atomic_write(&path, "two").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_write_follows_symlink() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let real = tmp_dir.path().join("dotfiles/real.json");
+        let link = tmp_dir.path().join("link.json");
+        let backups = tmp_dir.path().join("backups");
+        std::fs::create_dir_all(real.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink("dotfiles/real.json", &link).unwrap();
+        // リンク先がまだ無い場合もリンク先に作る
+        write_with_backup(&link, "one", Some(&backups)).unwrap();
+        write_with_backup(&link, "two", Some(&backups)).unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&real).unwrap(), "two");
+        let list = list_backups(&real, &backups).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(std::fs::read_to_string(&list[0]).unwrap(), "one");
+    }
+
+    #[test]
+    fn test_write_with_backup_rotates() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("mcp.json");
+        let backups = tmp_dir.path().join("backups");
+        write_with_backup(&path, "v0", Some(&backups)).unwrap();
+        assert!(list_backups(&path, &backups).unwrap().is_empty());
+        for i in 1..=MAX_BACKUPS + 2 {
+            write_with_backup(&path, &format!("v{}", i), Some(&backups)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        // 同じ内容ではバックアップを作らない
+        write_with_backup(&path, &format!("v{}", MAX_BACKUPS + 2), Some(&backups)).unwrap();
+        let list = list_backups(&path, &backups).unwrap();
+        assert_eq!(list.len(), MAX_BACKUPS);
+        assert_eq!(std::fs::read_to_string(list.last().unwrap()).unwrap(), format!("v{}", MAX_BACKUPS + 1));
+        assert_eq!(std::fs::read_to_string(&list[0]).unwrap(), "v2");
+        let other = tmp_dir.path().join("mcp.json.bak");
+        assert!(list_backups(&other, &backups).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::fsutil::*;
+use crate::model::*;
+
+// 環境ごとに適用したサーバの組み合わせを記録する
+
+/// 1環境あたりに残す履歴の数
+pub const MAX_HISTORY: usize = 50;
+
+/// 適用した時点のenableと、展開前のサーバ定義
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub enable: Vec<String>,
+    #[serde(serialize_with = "sorted_map")]
+    pub servers: HashMap<String, McpServerConfig>,
+}
+
+fn history_path(history_dir: &Path, env_name: &str) -> PathBuf {
+    let file_name: String = env_name.chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':') { '%' } else { c })
+        .collect();
+    history_dir.join(format!("{}.json", file_name))
+}
+
+/// 履歴を新しい順に返す（0番目が最後に適用した状態）
+pub fn load_history(history_dir: &Path, env_name: &str) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+    let path = history_path(history_dir, env_name);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries: Vec<HistoryEntry> = serde_json::from_str(&content)?;
+    entries.reverse();
+    Ok(entries)
+}
+
+/// 適用した状態を履歴に追加する。直前と同じ状態は追加しない
+pub fn record_history(history_dir: &Path, env_name: &str, enable: &[String], servers: &HashMap<String, McpServerConfig>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entries = load_history(history_dir, env_name)?;
+    if entries.first().is_some_and(|e| e.enable == enable && &e.servers == servers) {
+        return Ok(());
+    }
+    entries.insert(0, HistoryEntry {
+        timestamp: timestamp_now(),
+        enable: enable.to_vec(),
+        servers: servers.clone(),
+    });
+    entries.truncate(MAX_HISTORY);
+    entries.reverse();
+    atomic_write(&history_path(history_dir, env_name), &serde_json::to_string_pretty(&entries)?)
+}
+
+/// 履歴の1行表示（"n  日時  サーバ名, ..."）
+pub fn history_line(n: usize, entry: &HistoryEntry) -> String {
+    let servers = if entry.enable.is_empty() { "(none)".to_string() } else { entry.enable.join(", ") };
+    format!("{:>3}  {}  {}", n, display_timestamp(&entry.timestamp), servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_load_history() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let servers = HashMap::from([("a".to_string(), McpServerConfig { command: "echo".to_string(), ..Default::default() })]);
+        assert!(load_history(tmp_dir.path(), "env/1").unwrap().is_empty());
+        record_history(tmp_dir.path(), "env/1", &["a".to_string()], &servers).unwrap();
+        record_history(tmp_dir.path(), "env/1", &["a".to_string()], &servers).unwrap();
+        record_history(tmp_dir.path(), "env/1", &[], &HashMap::new()).unwrap();
+        let entries = load_history(tmp_dir.path(), "env/1").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].enable.is_empty());
+        assert_eq!(entries[1].servers["a"].command, "echo");
+        assert!(history_line(1, &entries[1]).ends_with("  a"));
+        assert!(history_line(0, &entries[0]).ends_with("(none)"));
+        assert!(tmp_dir.path().join("env%1.json").exists());
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        for i in 0..MAX_HISTORY + 5 {
+            record_history(tmp_dir.path(), "env1", &[i.to_string()], &HashMap::new()).unwrap();
+        }
+        let entries = load_history(tmp_dir.path(), "env1").unwrap();
+        assert_eq!(entries.len(), MAX_HISTORY);
+        assert_eq!(entries[0].enable, vec![(MAX_HISTORY + 4).to_string()]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::model::*;
+use crate::targets::*;
+
+/// 取り込むサーバと同じ名前の定義が既にある場合の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Conflict {
+    /// 既存の定義を残す
+    Skip,
+    /// 名前に-2, -3...を付けて別のサーバとして追加する
+    Rename,
+    /// 取り込む定義で置き換える
+    Overwrite,
+}
+
+/// 取り込みの結果
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub env_name: String,
+    pub added: Vec<String>,
+    /// (元の名前, 追加した名前)
+    pub renamed: Vec<(String, String)>,
+    pub overwritten: Vec<String>,
+    pub skipped: Vec<String>,
+    /// 同じ内容の定義が既にあったもの
+    pub unchanged: Vec<String>,
+}
+
+impl ImportReport {
+    /// CLI向けの1サーバ1行の結果
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        lines.extend(self.added.iter().map(|n| format!("+ {}", n)));
+        lines.extend(self.renamed.iter().map(|(from, to)| format!("+ {} (renamed from {})", to, from)));
+        lines.extend(self.overwritten.iter().map(|n| format!("~ {} (overwritten)", n)));
+        lines.extend(self.skipped.iter().map(|n| format!("= {} (skipped, existing definition kept)", n)));
+        lines.extend(self.unchanged.iter().map(|n| format!("= {} (already defined)", n)));
+        lines
+    }
+
+    /// TUIのステータス行向けの要約
+    pub fn summary(&self) -> String {
+        let imported = self.added.len() + self.renamed.len() + self.overwritten.len();
+        let mut summary = format!("Imported {} servers into {}", imported, self.env_name);
+        let mut notes = Vec::new();
+        if !self.renamed.is_empty() {
+            notes.push(format!("{} renamed", self.renamed.len()));
+        }
+        if !self.skipped.is_empty() {
+            notes.push(format!("{} skipped", self.skipped.len()));
+        }
+        if !self.unchanged.is_empty() {
+            notes.push(format!("{} unchanged", self.unchanged.len()));
+        }
+        if !notes.is_empty() {
+            summary.push_str(&format!(" ({})", notes.join(", ")));
+        }
+        summary
+    }
+}
+
+/// 既存の名前と重ならない名前（name-2, name-3...）
+fn unused_name(cfg: &McpServersConfig, name: &str) -> String {
+    (2..).map(|n| format!("{}-{}", name, n))
+        .find(|candidate| !cfg.mcp_servers.contains_key(candidate))
+        .unwrap_or_default()
+}
+
+/// クライアントの設定ファイルからサーバ定義をmcpServersに取り込み、env_nameの環境で有効にする
+/// 環境が無ければmodeとpathを書き出し先として作成する（basic_config.jsonの保存は呼び出し側で行う）
+pub fn import_servers(
+    cfg: &mut McpServersConfig,
+    env_name: &str,
+    mode: &str,
+    project: Option<&str>,
+    path: &Path,
+    conflict: Conflict,
+) -> Result<ImportReport, Box<dyn std::error::Error>> {
+    if let Some(env_cfg) = cfg.environments.get(env_name)
+        && let Some(env_mode) = env_cfg.mode.as_deref()
+        && env_mode != mode {
+        return Err(format!("environment {} uses mode {}, not {}", env_name, env_mode, mode).into());
+    }
+    let writer = writer_for(mode, project)?;
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let servers: HashMap<String, McpServerConfig> = writer.read_back(&content)?;
+
+    let mut report = ImportReport { env_name: env_name.to_string(), ..Default::default() };
+    let mut enabled = Vec::new();
+    for name in sorted_keys(&servers) {
+        let server = servers[name].clone();
+        let name = match cfg.mcp_servers.get(name) {
+            None => {
+                report.added.push(name.clone());
+                name.clone()
+            },
+            Some(existing) if *existing == server => {
+                report.unchanged.push(name.clone());
+                name.clone()
+            },
+            Some(_) => match conflict {
+                Conflict::Skip => {
+                    report.skipped.push(name.clone());
+                    enabled.push(name.clone());
+                    continue;
+                },
+                Conflict::Rename => {
+                    let renamed = unused_name(cfg, name);
+                    report.renamed.push((name.clone(), renamed.clone()));
+                    renamed
+                },
+                Conflict::Overwrite => {
+                    report.overwritten.push(name.clone());
+                    name.clone()
+                },
+            },
+        };
+        cfg.mcp_servers.insert(name.clone(), server);
+        enabled.push(name);
+    }
+
+    let env_cfg = cfg.environments.entry(env_name.to_string()).or_default();
+    if env_cfg.mode.is_none() {
+        env_cfg.mode = Some(mode.to_string());
+    }
+    if env_cfg.config_path.is_empty() {
+        env_cfg.config_path = std::path::absolute(path)?.display().to_string();
+    }
+    if env_cfg.project.is_none() {
+        env_cfg.project = project.map(|p| p.to_string());
+    }
+    let enable = env_cfg.enable.get_or_insert_with(Vec::new);
+    for name in enabled {
+        if !enable.contains(&name) {
+            enable.push(name);
+        }
+    }
+    Ok(report)
+}
+
+/// TUIの入力欄の"<mode> <path> [skip|rename|overwrite]"を分解する（既定はrename）
+pub fn parse_import_input(input: &str) -> Result<(String, String, Conflict), Box<dyn std::error::Error>> {
+    let mut words: Vec<&str> = input.split_whitespace().collect();
+    let conflict = match words.last().copied() {
+        Some("skip") => Some(Conflict::Skip),
+        Some("rename") => Some(Conflict::Rename),
+        Some("overwrite") => Some(Conflict::Overwrite),
+        _ => None,
+    };
+    if conflict.is_some() {
+        words.pop();
+    }
+    if words.len() < 2 {
+        return Err("usage: <mode> <path> [skip|rename|overwrite]".into());
+    }
+    Ok((words[0].to_string(), words[1..].join(" "), conflict.unwrap_or(Conflict::Rename)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(command: &str) -> McpServerConfig {
+        McpServerConfig { command: command.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_import_servers_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mcp.json");
+        std::fs::write(&path, r#"{"mcpServers": {
+            "same": {"command": "echo"},
+            "fs": {"command": "npx", "args": ["-y", "fs"], "alwaysAllow": ["read"]},
+            "new": {"command": "cat"}
+        }}"#).unwrap();
+        let base = || McpServersConfig {
+            mcp_servers: HashMap::from([
+                ("same".to_string(), server("echo")),
+                ("fs".to_string(), server("node")),
+                ("fs-2".to_string(), server("node")),
+            ]),
+            ..Default::default()
+        };
+
+        let mut cfg = base();
+        let report = import_servers(&mut cfg, "cursor", "cursor", None, &path, Conflict::Rename).unwrap();
+        assert_eq!(report.added, vec!["new"]);
+        assert_eq!(report.renamed, vec![("fs".to_string(), "fs-3".to_string())]);
+        assert_eq!(report.unchanged, vec!["same"]);
+        assert_eq!(cfg.mcp_servers["fs"].command, "node");
+        assert_eq!(cfg.mcp_servers["fs-3"].extra["alwaysAllow"][0], "read");
+        let env_cfg = &cfg.environments["cursor"];
+        assert_eq!(env_cfg.mode.as_deref(), Some("cursor"));
+        assert_eq!(env_cfg.config_path, path.display().to_string());
+        assert_eq!(env_cfg.enable.as_ref().unwrap(), &vec!["fs-3", "new", "same"]);
+
+        let mut cfg = base();
+        let report = import_servers(&mut cfg, "cursor", "cursor", None, &path, Conflict::Skip).unwrap();
+        assert_eq!(report.skipped, vec!["fs"]);
+        assert_eq!(cfg.mcp_servers["fs"].command, "node");
+        assert!(!cfg.mcp_servers.contains_key("fs-3"));
+
+        let mut cfg = base();
+        let report = import_servers(&mut cfg, "cursor", "cursor", None, &path, Conflict::Overwrite).unwrap();
+        assert_eq!(report.overwritten, vec!["fs"]);
+        assert_eq!(cfg.mcp_servers["fs"].command, "npx");
+        assert_eq!(report.summary(), "Imported 2 servers into cursor (1 unchanged)");
+
+        // 既存の環境はenableに追加し、modeが違う場合はエラー
+        let mut cfg = base();
+        cfg.environments.insert("work".to_string(), EnvironmentConfig {
+            config_path: "/tmp/other.json".to_string(),
+            enable: Some(vec!["same".to_string(), "fs-2".to_string()]),
+            mode: Some("cursor".to_string()),
+            ..Default::default()
+        });
+        import_servers(&mut cfg, "work", "cursor", None, &path, Conflict::Skip).unwrap();
+        assert_eq!(cfg.environments["work"].config_path, "/tmp/other.json");
+        assert_eq!(cfg.environments["work"].enable.as_ref().unwrap(), &vec!["same", "fs-2", "fs", "new"]);
+        assert!(import_servers(&mut cfg, "work", "zed", None, &path, Conflict::Skip).is_err());
+    }
+
+    #[test]
+    fn test_parse_import_input() {
+        let (mode, path, conflict) = parse_import_input("cursor /tmp/my dir/mcp.json").unwrap();
+        assert_eq!((mode.as_str(), path.as_str(), conflict), ("cursor", "/tmp/my dir/mcp.json", Conflict::Rename));
+        let (_, _, conflict) = parse_import_input("zed /tmp/settings.json skip").unwrap();
+        assert_eq!(conflict, Conflict::Skip);
+        assert!(parse_import_input("cursor").is_err());
+    }
+}
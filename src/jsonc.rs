@@ -0,0 +1,197 @@
+use serde_json::Value;
+
+// コメント付きJSON（JSONC）を、コメントを保持したまま部分的に書き換えるための最小限のスキャナ
+
+fn skip_ws_and_comments(b: &[u8], mut i: usize) -> usize {
+    while i < b.len() {
+        match b[i] {
+            b' ' | b'\t' | b'\r' | b'\n' => i += 1,
+            b'/' if b.get(i + 1) == Some(&b'/') => {
+                while i < b.len() && b[i] != b'\n' { i += 1; }
+            },
+            b'/' if b.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < b.len() && !(b[i] == b'*' && b.get(i + 1) == Some(&b'/')) { i += 1; }
+                i = (i + 2).min(b.len());
+            },
+            _ => break,
+        }
+    }
+    i
+}
+
+/// iは開始の'"'を指す。閉じ'"'の次の位置を返す
+fn skip_string(b: &[u8], mut i: usize) -> Result<usize, Box<dyn std::error::Error>> {
+    i += 1;
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 2,
+            b'"' => return Ok(i + 1),
+            _ => i += 1,
+        }
+    }
+    Err("unterminated string in JSONC".into())
+}
+
+/// iは値の先頭を指す。値の直後の位置を返す
+fn skip_value(b: &[u8], i: usize) -> Result<usize, Box<dyn std::error::Error>> {
+    match b.get(i) {
+        Some(b'"') => skip_string(b, i),
+        Some(b'{') | Some(b'[') => {
+            let mut depth = 0;
+            let mut i = i;
+            while i < b.len() {
+                match b[i] {
+                    b'"' => { i = skip_string(b, i)?; continue; },
+                    b'/' if matches!(b.get(i + 1), Some(b'/') | Some(b'*')) => { i = skip_ws_and_comments(b, i); continue; },
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 { return Ok(i + 1); }
+                    },
+                    _ => {},
+                }
+                i += 1;
+            }
+            Err("unterminated object or array in JSONC".into())
+        },
+        Some(_) => {
+            let mut i = i;
+            while i < b.len() && !matches!(b[i], b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n' | b'/') { i += 1; }
+            Ok(i)
+        },
+        None => Err("unexpected end of JSONC".into()),
+    }
+}
+
+/// コメントと末尾カンマを取り除き、serde_jsonで読める文字列にする
+fn strip_comments(s: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let b = s.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'"' => {
+                let end = skip_string(b, i)?;
+                out.extend_from_slice(&b[i..end]);
+                i = end;
+            },
+            b'/' if matches!(b.get(i + 1), Some(b'/') | Some(b'*')) => {
+                i = skip_ws_and_comments(b, i);
+            },
+            b',' => {
+                let next = skip_ws_and_comments(b, i + 1);
+                if !matches!(b.get(next), Some(b'}') | Some(b']')) {
+                    out.push(b',');
+                }
+                i += 1;
+            },
+            c => {
+                out.push(c);
+                i += 1;
+            },
+        }
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+/// JSONCとして読み込む（空文字列は空オブジェクト扱い）
+pub fn parse(s: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    if s.trim().is_empty() {
+        return Ok(Value::Object(serde_json::Map::new()));
+    }
+    Ok(serde_json::from_str(&strip_comments(s)?)?)
+}
+
+/// ルートオブジェクトの指定キーの値だけを書き換える。キーが無ければ末尾に追加する
+/// それ以外の部分（コメント・書式）はそのまま残す
+pub fn set_top_level_key(s: &str, key: &str, value: &Value) -> Result<String, Box<dyn std::error::Error>> {
+    let rendered = serde_json::to_string_pretty(value)?.replace('\n', "\n  ");
+    if s.trim().is_empty() {
+        return Ok(format!("{{\n  {}: {}\n}}\n", serde_json::to_string(key)?, rendered));
+    }
+    let b = s.as_bytes();
+    let mut i = skip_ws_and_comments(b, 0);
+    if b.get(i) != Some(&b'{') {
+        return Err("target config is not a JSON object".into());
+    }
+    i += 1;
+    // 直前の有意な文字（カンマ要否の判定用）と最後の値の終端位置
+    let mut last_significant = b'{';
+    let mut last_value_end = i;
+    loop {
+        i = skip_ws_and_comments(b, i);
+        match b.get(i) {
+            Some(b'}') => {
+                let sep = if last_significant == b'{' || last_significant == b',' { "" } else { "," };
+                let mut out = String::with_capacity(s.len() + rendered.len());
+                // 最後の値の直後にカンマを置き、閉じ括弧の前に新しいキーを追加する
+                let insert_at = s[..i].trim_end().len();
+                out.push_str(&s[..last_value_end]);
+                out.push_str(sep);
+                out.push_str(&s[last_value_end..insert_at]);
+                out.push_str(&format!("\n  {}: {}\n", serde_json::to_string(key)?, rendered));
+                out.push_str(&s[i..]);
+                return Ok(out);
+            },
+            Some(b'"') => {
+                let key_end = skip_string(b, i)?;
+                let this_key: String = serde_json::from_str(&s[i..key_end])?;
+                let colon = skip_ws_and_comments(b, key_end);
+                if b.get(colon) != Some(&b':') {
+                    return Err("expected ':' in JSONC object".into());
+                }
+                let value_start = skip_ws_and_comments(b, colon + 1);
+                let value_end = skip_value(b, value_start)?;
+                if this_key == key {
+                    return Ok(format!("{}{}{}", &s[..value_start], rendered, &s[value_end..]));
+                }
+                i = skip_ws_and_comments(b, value_end);
+                last_significant = b'"';
+                last_value_end = value_end;
+                if b.get(i) == Some(&b',') {
+                    last_significant = b',';
+                    i += 1;
+                }
+            },
+            _ => return Err("unexpected token in JSONC object".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_with_comments_and_trailing_commas() {
+        let s = "// top\n{\n  \"a\": 1, /* inline */\n  \"b\": \"x // not a comment\",\n}\n";
+        let v = parse(s).unwrap();
+        assert_eq!(v["a"], 1);
+        assert_eq!(v["b"], "x // not a comment");
+    }
+
+    #[test]
+    fn test_set_top_level_key_replaces_value_only() {
+        let s = "{\n  // theme\n  \"theme\": \"One Dark\",\n  \"context_servers\": { \"old\": {} }, // managed\n  \"vim_mode\": true\n}\n";
+        let out = set_top_level_key(s, "context_servers", &serde_json::json!({"new": 1})).unwrap();
+        assert!(out.contains("// theme"));
+        assert!(out.contains("// managed"));
+        assert!(!out.contains("old"));
+        let v = parse(&out).unwrap();
+        assert_eq!(v["context_servers"]["new"], 1);
+        assert_eq!(v["vim_mode"], true);
+    }
+
+    #[test]
+    fn test_set_top_level_key_appends_missing_key() {
+        let s = "{\n  \"theme\": \"One Dark\" // trailing\n}\n";
+        let out = set_top_level_key(s, "context_servers", &serde_json::json!({})).unwrap();
+        assert!(out.contains("// trailing"));
+        let v = parse(&out).unwrap();
+        assert_eq!(v["theme"], "One Dark");
+        assert!(v["context_servers"].is_object());
+        let out = set_top_level_key("{}", "k", &serde_json::json!(1)).unwrap();
+        assert_eq!(parse(&out).unwrap()["k"], 1);
+    }
+}
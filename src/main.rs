@@ -1,11 +1,27 @@
 mod model;
+mod check;
 mod config;
+mod fsutil;
+mod history;
+mod import;
+mod jsonc;
+mod secret;
+mod targets;
+mod sync;
+mod diff;
+mod cli;
 mod tui;
 
+use clap::Parser;
 use config::*;
+use cli::*;
 use tui::*;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        return run_command(command);
+    }
     ensure_config()?;
     let config = load_config().ok();
     if config.is_none() {
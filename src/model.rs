@@ -1,40 +1,147 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// HashMapをキーの順に書き出す（内容が同じなら保存のたびに同じファイルになるように）
+pub fn sorted_map<S: Serializer, V: Serialize>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn sorted_opt_map<S: Serializer, V: Serialize>(map: &Option<HashMap<String, V>>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.as_ref().map(|m| m.iter().collect::<BTreeMap<_, _>>()).serialize(serializer)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct McpServersConfig {
-    #[serde(rename = "mcpServers")]
+    #[serde(rename = "mcpServers", serialize_with = "sorted_map")]
     pub mcp_servers: HashMap<String, McpServerConfig>,
-    #[serde(rename = "environments")]
+    #[serde(rename = "environments", serialize_with = "sorted_map")]
     pub environments: HashMap<String, EnvironmentConfig>,
+    /// 変数展開に使う.envファイル（プロセスの環境変数が優先）
+    #[serde(rename = "envFiles", default, skip_serializing_if = "Vec::is_empty")]
+    pub env_files: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Stdio,
+    Sse,
+    Http,
+}
+
+impl Transport {
+    pub fn is_stdio(&self) -> bool {
+        *self == Transport::Stdio
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Stdio => "stdio",
+            Transport::Sse => "sse",
+            Transport::Http => "http",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct McpServerConfig {
+    /// stdioの場合の起動コマンド（リモートサーバでは不要）
+    #[serde(default)]
     pub command: String,
+    #[serde(default)]
     pub args: Vec<String>,
+    #[serde(default, serialize_with = "sorted_map")]
     pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Transport::is_stdio")]
+    pub transport: Transport,
+    /// sse / httpの場合の接続先
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted_map")]
+    pub headers: HashMap<String, String>,
+    /// 作業ディレクトリ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// タイムアウト（ミリ秒。Gooseでは秒に換算して出力）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Gemini CLIでツール実行の確認を省略する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trust: Option<bool>,
+    /// MCPallete内だけで使う説明（ターゲットには出力しない）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MCPallete内だけで使うタグ（ターゲットには出力しない）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// シークレットとして画面表示で伏せるenv・headersのキー（*_KEY、*_TOKENなどは指定しなくても伏せる）
+    #[serde(rename = "secretEnv", default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_env: Vec<String>,
+    /// 上記以外のキー（alwaysAllowなどクライアント固有の設定）。そのままターゲットへ出力する
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// 環境ごとにサーバ定義の一部を差し替える設定
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ServerOverride {
+    /// 指定した場合はargsを丸ごと置き換える
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+    /// 元のenvに追加・上書きするキー
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted_map")]
+    pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+}
+
+impl ServerOverride {
+    /// サーバ定義に差分を適用する
+    pub fn apply(&self, server: &mut McpServerConfig) {
+        if let Some(args) = &self.args {
+            server.args = args.clone();
+        }
+        for (k, v) in &self.env {
+            server.env.insert(k.clone(), v.clone());
+        }
+        if let Some(cwd) = &self.cwd {
+            server.cwd = Some(cwd.clone());
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct EnvironmentConfig {
-    #[serde(rename = "configPath")]
+    #[serde(rename = "configPath", default)]
     pub config_path: String,
     pub enable: Option<Vec<String>>,
+    #[serde(serialize_with = "sorted_opt_map")]
     pub preset: Option<HashMap<String, Vec<String>>>,
     pub mode: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ClaudeDesktopConfig {
-    #[serde(rename = "mcpServers")]
-    pub mcp_servers: HashMap<String, McpServerConfig>,
+    /// falseの場合はターゲットファイルを丸ごと上書きする（既定はmcpServersのみ差し替え）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge: Option<bool>,
+    /// claude_codeモードで~/.claude.jsonのprojects配下を更新する場合のプロジェクトパス
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// サーバ名ごとの差し替え（生成時のみ適用し、mcpServersの定義は変えない）
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted_map")]
+    pub overrides: HashMap<String, ServerOverride>,
+    /// 環境変数展開しないフィールド（command, args, env, cwd, url, headers）
+    #[serde(rename = "skipExpand", default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_expand: Vec<String>,
+    /// falseの場合はenv・headersの値を展開せず${VAR}参照のまま書き出す（対応するmodeのみ）
+    #[serde(rename = "expandSecrets", default, skip_serializing_if = "Option::is_none")]
+    pub expand_secrets: Option<bool>,
+    /// この環境だけで使う.envファイル（トップレベルのenvFilesより優先）
+    #[serde(rename = "envFiles", default, skip_serializing_if = "Vec::is_empty")]
+    pub env_files: Vec<String>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json;
 
     #[test]
     fn test_serialize_deserialize_mcpserversconfig() {
@@ -52,4 +159,61 @@ mod tests {
         let out = serde_json::to_string(&cfg).unwrap();
         assert!(out.contains("mcpServers"));
     }
+
+    #[test]
+    fn test_server_optional_and_extra_fields() {
+        let json = r#"{"command": "npx", "description": "Files", "tags": ["local"], "cwd": "/work", "alwaysAllow": ["read_file"]}"#;
+        let server: McpServerConfig = serde_json::from_str(json).unwrap();
+        assert!(server.args.is_empty());
+        assert!(server.env.is_empty());
+        assert_eq!(server.description.as_deref(), Some("Files"));
+        assert_eq!(server.tags, vec!["local"]);
+        assert_eq!(server.extra["alwaysAllow"][0], "read_file");
+        let out = serde_json::to_value(&server).unwrap();
+        assert_eq!(out["alwaysAllow"][0], "read_file");
+        assert!(out.get("extra").is_none());
+    }
+
+    #[test]
+    fn test_serialize_is_sorted() {
+        // HashMapは作るたびにキーの順序が変わるので、同じ内容を作り直して比べる
+        let config = || {
+            let names = ["e", "b", "d", "a", "c", "f"];
+            let server = McpServerConfig {
+                command: "npx".to_string(),
+                env: names.iter().map(|n| (n.to_uppercase(), n.to_string())).collect(),
+                ..Default::default()
+            };
+            McpServersConfig {
+                mcp_servers: names.iter().map(|n| (n.to_string(), server.clone())).collect(),
+                environments: names.iter().map(|n| (n.to_string(), EnvironmentConfig {
+                    preset: Some(names.iter().map(|p| (p.to_string(), vec![])).collect()),
+                    ..Default::default()
+                })).collect(),
+                ..Default::default()
+            }
+        };
+        let first = serde_json::to_string_pretty(&config()).unwrap();
+        for _ in 0..4 {
+            assert_eq!(first, serde_json::to_string_pretty(&config()).unwrap());
+        }
+        assert!(first.find("\"a\"").unwrap() < first.find("\"b\"").unwrap());
+    }
+
+    #[test]
+    fn test_server_override_apply() {
+        let mut server = McpServerConfig {
+            command: "npx".to_string(),
+            args: vec!["-y".to_string(), "/home".to_string()],
+            env: HashMap::from([("A".to_string(), "1".to_string()), ("B".to_string(), "2".to_string())]),
+            ..Default::default()
+        };
+        let patch: ServerOverride = serde_json::from_str(r#"{"args": ["-y", "/work"], "env": {"B": "3"}, "cwd": "/work"}"#).unwrap();
+        patch.apply(&mut server);
+        assert_eq!(server.args, vec!["-y", "/work"]);
+        assert_eq!(server.env["A"], "1");
+        assert_eq!(server.env["B"], "3");
+        assert_eq!(server.cwd.as_deref(), Some("/work"));
+        assert_eq!(server.command, "npx");
+    }
 }
\ No newline at end of file
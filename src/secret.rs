@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use serde_json::{Map, Value};
+use crate::config::*;
+use crate::model::*;
+use crate::targets::*;
+
+// 画面やログに出すサーバ定義のシークレットを伏せる
+
+/// 伏せた値の表示
+pub const MASK: &str = "********";
+
+/// キー名の末尾でシークレットとみなすもの（大文字・"-"を"_"に正規化して比較）
+const SECRET_SUFFIXES: [&str; 5] = ["_KEY", "_TOKEN", "_SECRET", "_PASSWORD", "_CREDENTIALS"];
+
+/// キー名そのものでシークレットとみなすもの
+const SECRET_NAMES: [&str; 6] = ["KEY", "TOKEN", "SECRET", "PASSWORD", "AUTHORIZATION", "COOKIE"];
+
+/// env・headersのキーがシークレットか。explicitはサーバのsecretEnv
+pub fn is_secret_key(key: &str, explicit: &[String]) -> bool {
+    if explicit.iter().any(|k| k == key) {
+        return true;
+    }
+    let key = key.to_uppercase().replace('-', "_");
+    SECRET_NAMES.contains(&key.as_str()) || SECRET_SUFFIXES.iter().any(|s| key.ends_with(s))
+}
+
+/// 変数参照（$VAR、${cmd:...}など）を含むか
+pub fn has_var_refs(s: &str) -> bool {
+    let mut found = false;
+    let _ = replace_var_refs(s, |r| {
+        found = true;
+        Ok(r.text.to_string())
+    });
+    found
+}
+
+/// シークレットの値を伏せる。参照のままの値は秘密ではないのでそのまま表示する
+pub fn mask_value(value: &str) -> String {
+    if value.is_empty() || has_var_refs(value) {
+        value.to_string()
+    } else {
+        MASK.to_string()
+    }
+}
+
+/// シークレットとみなすenv・headersの値を伏せたサーバ定義
+pub fn masked_server(server: &McpServerConfig) -> McpServerConfig {
+    let mask_map = |map: &HashMap<String, String>| -> HashMap<String, String> {
+        map.iter()
+            .map(|(k, v)| {
+                let v = if is_secret_key(k, &server.secret_env) { mask_value(v) } else { v.clone() };
+                (k.clone(), v)
+            })
+            .collect()
+    };
+    McpServerConfig {
+        env: mask_map(&server.env),
+        headers: mask_map(&server.headers),
+        ..server.clone()
+    }
+}
+
+/// 表示用のJSON。シークレットを伏せ、env・headersはキー順に並べる
+pub fn masked_value(server: &McpServerConfig) -> Result<Value, Box<dyn std::error::Error>> {
+    let masked = masked_server(server);
+    let mut value = serde_json::to_value(&masked)?;
+    if let Value::Object(obj) = &mut value {
+        for (key, map) in [("env", &masked.env), ("headers", &masked.headers)] {
+            if obj.contains_key(key) {
+                obj.insert(key.to_string(), Value::Object(env_value(map)));
+            }
+        }
+    }
+    Ok(value)
+}
+
+/// 名前順に並べた表示用のJSON
+pub fn masked_servers_value(servers: &HashMap<String, McpServerConfig>) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut map = Map::new();
+    for name in sorted_keys(servers) {
+        map.insert(name.clone(), masked_value(&servers[name])?);
+    }
+    Ok(Value::Object(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_secret_key() {
+        assert!(is_secret_key("FIRECRAWL_API_KEY", &[]));
+        assert!(is_secret_key("github_token", &[]));
+        assert!(is_secret_key("Authorization", &[]));
+        assert!(is_secret_key("X-Api-Key", &[]));
+        assert!(!is_secret_key("KEYBOARD_LAYOUT", &[]));
+        assert!(!is_secret_key("DB_URL", &[]));
+        assert!(is_secret_key("DB_URL", &["DB_URL".to_string()]));
+    }
+
+    #[test]
+    fn test_masked_server() {
+        let server = McpServerConfig {
+            command: "npx".to_string(),
+            env: HashMap::from([
+                ("API_KEY".to_string(), "sk-123".to_string()),
+                ("GITHUB_TOKEN".to_string(), "$GITHUB_TOKEN".to_string()),
+                ("DB_URL".to_string(), "postgres://u:p@h/db".to_string()),
+                ("MODE".to_string(), "dev".to_string()),
+            ]),
+            headers: HashMap::from([("Authorization".to_string(), "Bearer abc".to_string())]),
+            secret_env: vec!["DB_URL".to_string()],
+            ..Default::default()
+        };
+        let masked = masked_server(&server);
+        assert_eq!(masked.env["API_KEY"], MASK);
+        assert_eq!(masked.env["GITHUB_TOKEN"], "$GITHUB_TOKEN");
+        assert_eq!(masked.env["DB_URL"], MASK);
+        assert_eq!(masked.env["MODE"], "dev");
+        assert_eq!(masked.headers["Authorization"], MASK);
+        assert_eq!(masked.command, "npx");
+        let value = masked_value(&server).unwrap();
+        assert_eq!(value["env"].as_object().unwrap().keys().collect::<Vec<_>>(), vec!["API_KEY", "DB_URL", "GITHUB_TOKEN", "MODE"]);
+        assert!(!value.to_string().contains("sk-123"));
+    }
+}
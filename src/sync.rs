@@ -0,0 +1,632 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use crate::config::*;
+use crate::fsutil::*;
+use crate::history::*;
+use crate::secret::*;
+use crate::targets::*;
+use crate::model::*;
+
+/// enableリストに含まれるサーバ定義を展開せずに取り出す
+pub fn enabled_server_defs(cfg: &McpServersConfig, enabled: &[String]) -> HashMap<String, McpServerConfig> {
+    enabled.iter()
+        .filter_map(|name| cfg.mcp_servers.get(name).map(|v| (name.clone(), v.clone())))
+        .collect()
+}
+
+/// 環境のoverridesをサーバ定義に適用する
+pub fn apply_overrides(env_cfg: &EnvironmentConfig, mut servers: HashMap<String, McpServerConfig>) -> HashMap<String, McpServerConfig> {
+    for (name, server) in servers.iter_mut() {
+        if let Some(patch) = env_cfg.overrides.get(name) {
+            patch.apply(server);
+        }
+    }
+    servers
+}
+
+/// 環境変数展開の対象になるフィールド
+pub const EXPANDABLE_FIELDS: [&str; 6] = ["command", "args", "env", "cwd", "url", "headers"];
+
+/// シークレットとして扱うフィールド
+const SECRET_FIELDS: [&str; 2] = ["env", "headers"];
+
+/// env・headersの変数参照の扱い
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SecretRefs {
+    /// 値に展開する
+    Expand,
+    /// ${VAR}参照の形で残す（expandSecrets: false）
+    Keep,
+    /// ${cmd:...} / ${file:...}だけを解決し、他の参照は書かれたまま残す（writerが参照を変換する形式）
+    Raw,
+}
+
+/// サーバ定義の文字列フィールドを環境変数展開する。skipに含まれるフィールドはそのまま残す
+/// env・headersの参照はrefsに従って扱う
+fn expand_server(server: &mut McpServerConfig, ctx: &VarContext, skip: &[&str], refs: SecretRefs, missing: &mut Vec<MissingVar>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut expand = |field: &str, s: &mut String| -> Result<(), Box<dyn std::error::Error>> {
+        if skip.contains(&field) {
+            return Ok(());
+        }
+        *s = match refs {
+            SecretRefs::Keep if SECRET_FIELDS.contains(&field) => ctx.keep_refs(s, missing)?,
+            SecretRefs::Raw if SECRET_FIELDS.contains(&field) => ctx.resolve_external_refs(s, missing)?,
+            _ => ctx.resolve(s, missing)?,
+        };
+        Ok(())
+    };
+    expand("command", &mut server.command)?;
+    for arg in server.args.iter_mut() {
+        expand("args", arg)?;
+    }
+    for value in server.env.values_mut() {
+        expand("env", value)?;
+    }
+    if let Some(cwd) = server.cwd.as_mut() {
+        expand("cwd", cwd)?;
+    }
+    if let Some(url) = server.url.as_mut() {
+        expand("url", url)?;
+    }
+    for value in server.headers.values_mut() {
+        expand("headers", value)?;
+    }
+    Ok(())
+}
+
+/// 全サーバを展開する。${VAR:?}の変数が未設定の場合は、変数名と必要とするサーバをまとめてエラーにする
+pub fn expand_servers(servers: HashMap<String, McpServerConfig>, ctx: &VarContext, skip: &[&str], refs: SecretRefs) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+    if let Some(field) = skip.iter().find(|f| !EXPANDABLE_FIELDS.contains(f)) {
+        return Err(format!("unknown field in skipExpand: {}", field).into());
+    }
+    let mut servers: Vec<(String, McpServerConfig)> = servers.into_iter().collect();
+    servers.sort_by(|a, b| a.0.cmp(&b.0));
+    // 変数名 -> (メッセージ, 必要とするサーバ)
+    let mut missing_by_var: BTreeMap<String, (MissingVar, Vec<String>)> = BTreeMap::new();
+    for (name, server) in servers.iter_mut() {
+        let mut missing = Vec::new();
+        expand_server(server, ctx, skip, refs, &mut missing)?;
+        for m in missing {
+            let entry = missing_by_var.entry(m.name.clone()).or_insert_with(|| (m, vec![]));
+            if !entry.1.contains(name) {
+                entry.1.push(name.clone());
+            }
+        }
+    }
+    if !missing_by_var.is_empty() {
+        let lines: Vec<String> = missing_by_var.values()
+            .map(|(m, names)| format!("  {} (needed by {})", m, names.join(", ")))
+            .collect();
+        return Err(format!("missing required variables:\n{}", lines.join("\n")).into());
+    }
+    Ok(servers.into_iter().collect())
+}
+
+/// ファイルを読み込む。存在しない場合は空文字列を返す
+pub fn read_existing(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// configPathを環境変数展開して使う。空の場合はwriterの既定パスを使う
+pub fn target_path(env_cfg: &EnvironmentConfig, writer: &dyn TargetWriter, ctx: &VarContext) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    if !env_cfg.config_path.is_empty() {
+        return Ok(Some(PathBuf::from(ctx.expand(&env_cfg.config_path)?)));
+    }
+    let Ok(home) = std::env::var("HOME") else {
+        return Ok(None);
+    };
+    Ok(writer.default_path(&PathBuf::from(home), env_cfg.project.as_deref()))
+}
+
+/// 環境の変数解決元。トップレベルのenvFiles、環境のenvFilesの順に読み込む（後のものが優先）
+/// 複数の環境を続けて扱う場合は同じcacheを渡す
+pub fn env_context(cfg: &McpServersConfig, env_cfg: &EnvironmentConfig, cache: &RefCache) -> Result<VarContext, Box<dyn std::error::Error>> {
+    Ok(VarContext::from_env_files(cfg.env_files.iter().chain(env_cfg.env_files.iter()))?.with_cache(cache))
+}
+
+/// 環境の書き出し先とその形式
+pub struct EnvTarget {
+    pub writer: Box<dyn TargetWriter>,
+    pub path: PathBuf,
+}
+
+/// 環境のmodeに対応するwriterと書き出し先。modeが無い環境はターゲットを持たない
+pub fn env_target(env_cfg: &EnvironmentConfig, ctx: &VarContext) -> Result<Option<EnvTarget>, Box<dyn std::error::Error>> {
+    let Some(mode) = env_cfg.mode.as_deref() else {
+        return Ok(None);
+    };
+    let writer = writer_for(mode, env_cfg.project.as_deref())?;
+    let path = target_path(env_cfg, writer.as_ref(), ctx)?
+        .ok_or_else(|| format!("configPath is required for mode {}", mode))?;
+    Ok(Some(EnvTarget { writer, path }))
+}
+
+/// overrides適用済みのサーバ定義を、環境の設定（skipExpand・expandSecrets）とwriterに合わせて展開する
+pub fn resolve_servers(env_cfg: &EnvironmentConfig, writer: Option<&dyn TargetWriter>, servers: HashMap<String, McpServerConfig>, ctx: &VarContext) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+    let keep_refs = env_cfg.expand_secrets == Some(false);
+    if keep_refs && !writer.is_some_and(|w| w.supports_env_refs()) {
+        return Err(format!("mode {} does not support expandSecrets: false", env_cfg.mode.as_deref().unwrap_or("-")).into());
+    }
+    let refs = if writer.is_some_and(|w| w.converts_env_refs()) {
+        SecretRefs::Raw
+    } else if keep_refs {
+        SecretRefs::Keep
+    } else {
+        SecretRefs::Expand
+    };
+    let skip: Vec<&str> = env_cfg.skip_expand.iter().map(|s| s.as_str()).collect();
+    expand_servers(servers, ctx, &skip, refs)
+}
+
+/// 環境に書き出されるサーバ定義（overridesと展開を適用済み）
+pub fn env_servers(cfg: &McpServersConfig, env_name: &str, names: &[String]) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+    let env_cfg = cfg.environments.get(env_name)
+        .ok_or_else(|| format!("unknown environment: {}", env_name))?;
+    let ctx = env_context(cfg, env_cfg, &RefCache::default())?;
+    let target = env_target(env_cfg, &ctx)?;
+    let servers = apply_overrides(env_cfg, enabled_server_defs(cfg, names));
+    resolve_servers(env_cfg, target.as_ref().map(|t| t.writer.as_ref()), servers, &ctx)
+}
+
+/// 平文のまま書き出されるシークレット（シークレットとみなすキーのうち、値が変数参照でないもの）を"サーバ.フィールド.キー"の形で返す
+fn plaintext_secrets(servers: &HashMap<String, McpServerConfig>) -> Vec<String> {
+    let mut found = Vec::new();
+    for name in sorted_keys(servers) {
+        let server = &servers[name];
+        for (field, map) in [("env", &server.env), ("headers", &server.headers)] {
+            for key in sorted_keys(map) {
+                let value = &map[key];
+                if is_secret_key(key, &server.secret_env) && !value.is_empty() && !has_var_refs(value) {
+                    found.push(format!("{}.{}.{}", name, field, key));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// pathを含むgitリポジトリのルート
+fn git_root(path: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    path.ancestors().skip(1).find(|dir| dir.join(".git").exists()).map(|dir| dir.to_path_buf())
+}
+
+/// 書き出す内容と、差分表示のための書き出し前後のサーバ定義
+pub struct TargetPlan {
+    pub path: PathBuf,
+    pub content: String,
+    /// 現在のファイルから読み戻したサーバ定義
+    pub current: HashMap<String, McpServerConfig>,
+    /// 書き出す内容から読み戻したサーバ定義
+    pub next: HashMap<String, McpServerConfig>,
+    pub warnings: Vec<String>,
+}
+
+/// 環境のmodeに応じて書き出す内容を作る（ファイルは書き換えない）
+/// serversは未展開の定義を受け取り、writerが必要とする場合だけ展開する
+pub fn plan_target(env_cfg: &EnvironmentConfig, servers: HashMap<String, McpServerConfig>, ctx: &VarContext) -> Result<Option<TargetPlan>, Box<dyn std::error::Error>> {
+    let Some(EnvTarget { writer, path }) = env_target(env_cfg, ctx)? else {
+        return Ok(None);
+    };
+    let servers = apply_overrides(env_cfg, servers);
+    let expanded = resolve_servers(env_cfg, Some(writer.as_ref()), servers, ctx)?;
+    let mut warnings = Vec::new();
+    let secrets = plaintext_secrets(&expanded);
+    if !secrets.is_empty()
+        && let Some(root) = git_root(&path) {
+        warnings.push(format!(
+            "plaintext secrets ({}) are written to {} inside the git repository {}",
+            secrets.join(", "), path.display(), root.display(),
+        ));
+    }
+    let existing = read_existing(&path)?;
+    let merge = env_cfg.merge.unwrap_or(true) || writer.always_merge();
+    let content = if merge {
+        writer.merge(&existing, &expanded)?
+    } else {
+        writer.render(&expanded)?
+    };
+    // 書き出す内容が読み戻せない場合はユーザーのファイルを壊さないよう中止する
+    let next = writer.read_back(&content)?;
+    // 上書きする場合は既存ファイルが壊れていても書き出せるので、差分は空の状態から取る
+    let current = writer.read_back(&existing).unwrap_or_default();
+    Ok(Some(TargetPlan { path, content, current, next, warnings }))
+}
+
+/// 環境のmodeに応じてconfigPathへサーバ定義を書き出す。戻り値は警告
+/// backup_dirを指定すると、既存のファイルを書き換える前にバックアップを取る
+pub fn write_target(env_cfg: &EnvironmentConfig, servers: HashMap<String, McpServerConfig>, ctx: &VarContext, backup_dir: Option<&Path>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let Some(plan) = plan_target(env_cfg, servers, ctx)? else {
+        return Ok(vec![]);
+    };
+    write_with_backup(&plan.path, &plan.content, backup_dir)?;
+    Ok(plan.warnings)
+}
+
+/// enabledで同期した場合に書き出す内容（プレビュー用）
+/// 差分表示でシークレットを伏せられるよう、読み戻した定義に元の定義のsecretEnvを補う
+pub fn plan_environment(cfg: &McpServersConfig, env_name: &str, enabled: &[String], cache: &RefCache) -> Result<Option<TargetPlan>, Box<dyn std::error::Error>> {
+    let env_cfg = cfg.environments.get(env_name)
+        .ok_or_else(|| format!("unknown environment: {}", env_name))?;
+    let ctx = env_context(cfg, env_cfg, cache)?;
+    let mut plan = plan_target(env_cfg, enabled_server_defs(cfg, enabled), &ctx)?;
+    if let Some(plan) = plan.as_mut() {
+        for (name, server) in plan.current.iter_mut().chain(plan.next.iter_mut()) {
+            if let Some(def) = cfg.mcp_servers.get(name) {
+                server.secret_env = def.secret_env.clone();
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// 環境のenableを更新し、ターゲットファイルとbasic_config.jsonの両方を書き出す。戻り値は警告
+pub fn sync_environment(cfg: &mut McpServersConfig, env_name: &str, enabled: Vec<String>, cache: &RefCache) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let servers = enabled_server_defs(cfg, &enabled);
+    apply_servers(cfg, env_name, enabled, servers, cache)
+}
+
+/// 環境名と、その環境の同期結果（成功時は警告）
+pub type EnvSyncResult = (String, Result<Vec<String>, Box<dyn std::error::Error>>);
+
+/// 全環境をそれぞれの保存済みのenableで同期する（環境名順）。失敗した環境があっても残りの環境は続ける
+/// ${cmd:...} / ${file:...} は全環境を通して1回だけ解決する（cacheに解決済みの値はそれを使う）
+pub fn sync_all(cfg: &mut McpServersConfig, cache: &RefCache) -> Vec<EnvSyncResult> {
+    let mut envs: Vec<(String, Vec<String>)> = cfg.environments.iter()
+        .map(|(name, env_cfg)| (name.clone(), env_cfg.enable.clone().unwrap_or_default()))
+        .collect();
+    envs.sort();
+    envs.into_iter()
+        .map(|(name, enabled)| {
+            let result = sync_environment(cfg, &name, enabled, cache);
+            (name, result)
+        })
+        .collect()
+}
+
+/// 履歴のn番目（0が最後に適用した状態）の状態に戻す。サーバ定義も記録した時点のものを書き出す
+/// 次のsyncで元に戻らないよう、記録した時点から変わったサーバ定義はmcpServersにも書き戻す
+pub fn rollback_environment(cfg: &mut McpServersConfig, env_name: &str, n: usize) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let entry = load_history(&get_history_dir(), env_name)?
+        .into_iter()
+        .nth(n)
+        .ok_or_else(|| format!("no history entry {} for {}", n, env_name))?;
+    let restored: Vec<String> = sorted_keys(&entry.servers).into_iter()
+        .filter(|name| cfg.mcp_servers.get(*name) != Some(&entry.servers[*name]))
+        .cloned()
+        .collect();
+    // 書き出しに失敗した場合は呼び出し側の設定を変えない
+    let mut next = cfg.clone();
+    for name in &restored {
+        next.mcp_servers.insert(name.clone(), entry.servers[name].clone());
+    }
+    let mut warnings = apply_servers(&mut next, env_name, entry.enable, entry.servers, &RefCache::default())?;
+    *cfg = next;
+    if !restored.is_empty() {
+        warnings.push(format!("restored the definitions of {} in mcpServers", restored.join(", ")));
+    }
+    Ok(warnings)
+}
+
+/// serversをターゲットファイルに書き出し、enableの更新と履歴の記録を行う
+fn apply_servers(cfg: &mut McpServersConfig, env_name: &str, enabled: Vec<String>, servers: HashMap<String, McpServerConfig>, cache: &RefCache) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let env_cfg = cfg.environments.get(env_name)
+        .ok_or_else(|| format!("unknown environment: {}", env_name))?;
+    let ctx = env_context(cfg, env_cfg, cache)?;
+    let warnings = write_target(env_cfg, servers.clone(), &ctx, Some(&get_backup_dir()))?;
+    let env_cfg = cfg.environments.get_mut(env_name)
+        .ok_or_else(|| format!("unknown environment: {}", env_name))?;
+    env_cfg.enable = Some(enabled.clone());
+    save_config(cfg)?;
+    record_history(&get_history_dir(), env_name, &enabled, &servers)?;
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn sample_config(config_path: &str) -> McpServersConfig {
+        let mut mcp_servers = HashMap::new();
+        mcp_servers.insert("a".to_string(), McpServerConfig {
+            command: "echo".to_string(),
+            args: vec!["hi".to_string()],
+            env: HashMap::from([("TOKEN".to_string(), "$SYNC_TEST_TOKEN".to_string())]),
+            ..Default::default()
+        });
+        mcp_servers.insert("b".to_string(), McpServerConfig {
+            command: "cat".to_string(),
+            args: vec![],
+            env: HashMap::new(),
+            ..Default::default()
+        });
+        let mut environments = HashMap::new();
+        environments.insert("env1".to_string(), EnvironmentConfig {
+            config_path: config_path.to_string(),
+            enable: None,
+            preset: None,
+            mode: Some("claude_desktop".to_string()),
+            ..Default::default()
+        });
+        McpServersConfig { mcp_servers, environments, ..Default::default() }
+    }
+
+    #[test]
+    fn test_expand_servers() {
+        unsafe { std::env::set_var("SYNC_TEST_TOKEN", "secret"); }
+        let cfg = sample_config("");
+        let servers = expand_servers(enabled_server_defs(&cfg, &["a".to_string(), "missing".to_string()]), &VarContext::default(), &[], SecretRefs::Expand).unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers["a"].env["TOKEN"], "secret");
+    }
+
+    #[test]
+    fn test_expand_servers_command_args_cwd() {
+        unsafe { std::env::set_var("SYNC_TEST_BIN", "/opt/node/bin"); }
+        unsafe { std::env::set_var("SYNC_TEST_ROOT", "/home/tester"); }
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "${SYNC_TEST_BIN}/npx".to_string(),
+            args: vec!["$SYNC_TEST_ROOT/projects".to_string()],
+            cwd: Some("$SYNC_TEST_ROOT".to_string()),
+            ..Default::default()
+        })]);
+        let expanded = expand_servers(servers.clone(), &VarContext::default(), &[], SecretRefs::Expand).unwrap();
+        assert_eq!(expanded["a"].command, "/opt/node/bin/npx");
+        assert_eq!(expanded["a"].args, vec!["/home/tester/projects"]);
+        assert_eq!(expanded["a"].cwd.as_deref(), Some("/home/tester"));
+        let skipped = expand_servers(servers.clone(), &VarContext::default(), &["args", "cwd"], SecretRefs::Expand).unwrap();
+        assert_eq!(skipped["a"].command, "/opt/node/bin/npx");
+        assert_eq!(skipped["a"].args, vec!["$SYNC_TEST_ROOT/projects"]);
+        assert_eq!(skipped["a"].cwd.as_deref(), Some("$SYNC_TEST_ROOT"));
+        assert!(expand_servers(servers, &VarContext::default(), &["argv"], SecretRefs::Expand).unwrap_err().to_string().contains("argv"));
+    }
+
+    #[test]
+    fn test_expand_servers_remote_templating() {
+        unsafe { std::env::set_var("SYNC_TEST_HOST", "example.com"); }
+        unsafe { std::env::set_var("SYNC_TEST_BEARER", "abc"); }
+        let servers = HashMap::from([("r".to_string(), McpServerConfig {
+            transport: Transport::Sse,
+            url: Some("https://${SYNC_TEST_HOST}/sse".to_string()),
+            headers: HashMap::from([("Authorization".to_string(), "Bearer $SYNC_TEST_BEARER".to_string())]),
+            ..Default::default()
+        })]);
+        let servers = expand_servers(servers, &VarContext::default(), &[], SecretRefs::Expand).unwrap();
+        assert_eq!(servers["r"].url.as_deref(), Some("https://example.com/sse"));
+        assert_eq!(servers["r"].headers["Authorization"], "Bearer abc");
+    }
+
+    #[test]
+    fn test_expand_servers_missing_required() {
+        let server = |value: &str| McpServerConfig {
+            command: "npx".to_string(),
+            env: HashMap::from([("KEY".to_string(), value.to_string())]),
+            ..Default::default()
+        };
+        let servers = HashMap::from([
+            ("b".to_string(), server("${SYNC_TEST_UNSET_KEY:?create a key}")),
+            ("a".to_string(), server("${SYNC_TEST_UNSET_KEY:?create a key}-${SYNC_TEST_UNSET_OTHER:?}")),
+            ("c".to_string(), server("${SYNC_TEST_UNSET_KEY:-none}")),
+        ]);
+        let err = expand_servers(servers, &VarContext::default(), &[], SecretRefs::Expand).unwrap_err().to_string();
+        assert_eq!(err, "missing required variables:\n  SYNC_TEST_UNSET_KEY: create a key (needed by a, b)\n  SYNC_TEST_UNSET_OTHER is not set (needed by a)");
+    }
+
+    #[test]
+    fn test_write_target_claude_desktop() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("claude_desktop_config.json");
+        let cfg = sample_config(path.to_str().unwrap());
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), None).unwrap();
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["mcpServers"].as_object().unwrap().keys().collect::<Vec<_>>(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_write_target_backs_up_existing_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("claude_desktop_config.json");
+        let backups = tmp_dir.path().join("backups");
+        let cfg = sample_config(path.to_str().unwrap());
+        std::fs::write(&path, r#"{"globalShortcut": "Alt+Space"}"#).unwrap();
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), Some(&backups)).unwrap();
+        let list = list_backups(&path, &backups).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(std::fs::read_to_string(&list[0]).unwrap(), r#"{"globalShortcut": "Alt+Space"}"#);
+        // 内容が変わらない場合は書き込みもバックアップもしない
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), Some(&backups)).unwrap();
+        assert_eq!(list_backups(&path, &backups).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_write_target_merges_existing_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("claude_desktop_config.json");
+        std::fs::write(&path, r#"{"globalShortcut": "Alt+Space"}"#).unwrap();
+        let mut cfg = sample_config(path.to_str().unwrap());
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), None).unwrap();
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["globalShortcut"], "Alt+Space");
+        assert_eq!(value["mcpServers"]["b"]["command"], "cat");
+        cfg.environments.get_mut("env1").unwrap().merge = Some(false);
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), None).unwrap();
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(value.get("globalShortcut").is_none());
+    }
+
+    #[test]
+    fn test_write_target_claude_code_project_always_merges() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join(".claude.json");
+        std::fs::write(&path, r#"{"numStartups": 3}"#).unwrap();
+        let mut cfg = sample_config(path.to_str().unwrap());
+        let env_cfg = cfg.environments.get_mut("env1").unwrap();
+        env_cfg.mode = Some("claude_code".to_string());
+        env_cfg.project = Some("/work/a".to_string());
+        env_cfg.merge = Some(false);
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), None).unwrap();
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["numStartups"], 3);
+        assert_eq!(value["projects"]["/work/a"]["mcpServers"]["b"]["command"], "cat");
+    }
+
+    #[test]
+    fn test_write_target_cursor_creates_parent_dir() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let mut cfg = sample_config("");
+        let env_cfg = cfg.environments.get_mut("env1").unwrap();
+        env_cfg.mode = Some("cursor".to_string());
+        env_cfg.project = Some(tmp_dir.path().to_str().unwrap().to_string());
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), None).unwrap();
+        let path = tmp_dir.path().join(".cursor/mcp.json");
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["mcpServers"]["b"]["command"], "cat");
+    }
+
+    #[test]
+    fn test_write_target_vscode_keeps_references() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("mcp.json");
+        let mut cfg = sample_config(path.to_str().unwrap());
+        cfg.environments.get_mut("env1").unwrap().mode = Some("vscode".to_string());
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["a".to_string()]), &VarContext::default(), None).unwrap();
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["servers"]["a"]["env"]["TOKEN"], "${input:SYNC_TEST_TOKEN}");
+
+        // ${cmd:...}は解決し、${VAR:?}の変数が未設定ならエラー
+        let servers = HashMap::from([("c".to_string(), McpServerConfig {
+            command: "echo".to_string(),
+            env: HashMap::from([
+                ("KEY".to_string(), "${cmd:echo s3cret}".to_string()),
+                ("MODE".to_string(), "${SYNC_TEST_MODE:-dev}".to_string()),
+                ("PRICE".to_string(), "$$5".to_string()),
+            ]),
+            ..Default::default()
+        })]);
+        write_target(&cfg.environments["env1"], servers.clone(), &VarContext::default(), None).unwrap();
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["servers"]["c"]["env"], serde_json::json!({"KEY": "s3cret", "MODE": "${input:SYNC_TEST_MODE}", "PRICE": "$5"}));
+        let mode = value["inputs"].as_array().unwrap().iter().find(|i| i["id"] == "SYNC_TEST_MODE").unwrap();
+        assert_eq!(mode["default"], "dev");
+        let mut required = servers;
+        required.get_mut("c").unwrap().env.insert("URL".to_string(), "${SYNC_TEST_UNSET_URL:?set the url}".to_string());
+        let err = write_target(&cfg.environments["env1"], required, &VarContext::default(), None).unwrap_err().to_string();
+        assert!(err.contains("SYNC_TEST_UNSET_URL: set the url"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_target_applies_overrides() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("claude_desktop_config.json");
+        let mut cfg = sample_config(path.to_str().unwrap());
+        cfg.environments.get_mut("env1").unwrap().overrides = HashMap::from([("b".to_string(), ServerOverride {
+            args: Some(vec!["/work".to_string()]),
+            env: HashMap::from([("ROOT".to_string(), "/work".to_string())]),
+            ..Default::default()
+        })]);
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), None).unwrap();
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["mcpServers"]["b"]["args"][0], "/work");
+        assert_eq!(value["mcpServers"]["b"]["env"]["ROOT"], "/work");
+        assert!(cfg.mcp_servers["b"].args.is_empty());
+    }
+
+    #[test]
+    fn test_env_context_env_files_override_top_level() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let top = tmp_dir.path().join("top.env");
+        let local = tmp_dir.path().join("local.env");
+        std::fs::write(&top, "SYNC_TEST_FILE_KEY=top\nSYNC_TEST_FILE_ONLY_TOP=1\n").unwrap();
+        std::fs::write(&local, "SYNC_TEST_FILE_KEY=local\n").unwrap();
+        let path = tmp_dir.path().join("claude_desktop_config.json");
+        let mut cfg = sample_config(path.to_str().unwrap());
+        cfg.env_files = vec![top.to_str().unwrap().to_string()];
+        cfg.environments.get_mut("env1").unwrap().env_files = vec![local.to_str().unwrap().to_string()];
+        cfg.mcp_servers.get_mut("b").unwrap().env = HashMap::from([
+            ("KEY".to_string(), "$SYNC_TEST_FILE_KEY".to_string()),
+            ("TOP".to_string(), "$SYNC_TEST_FILE_ONLY_TOP".to_string()),
+        ]);
+        let ctx = env_context(&cfg, &cfg.environments["env1"], &RefCache::default()).unwrap();
+        write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &ctx, None).unwrap();
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["mcpServers"]["b"]["env"]["KEY"], "local");
+        assert_eq!(value["mcpServers"]["b"]["env"]["TOP"], "1");
+    }
+
+    #[test]
+    fn test_target_path_expands_config_path() {
+        unsafe { std::env::set_var("SYNC_TEST_DIR", "/tmp/sync-test"); }
+        let cfg = sample_config("$SYNC_TEST_DIR/claude.json");
+        let writer = writer_for("claude_desktop", None).unwrap();
+        let path = target_path(&cfg.environments["env1"], writer.as_ref(), &VarContext::default()).unwrap();
+        assert_eq!(path, Some(PathBuf::from("/tmp/sync-test/claude.json")));
+        let cfg = sample_config("${SYNC_TEST_UNSET_DIR:?}/claude.json");
+        assert!(target_path(&cfg.environments["env1"], writer.as_ref(), &VarContext::default()).is_err());
+    }
+
+    #[test]
+    fn test_write_target_keeps_secret_refs() {
+        unsafe { std::env::set_var("SYNC_TEST_TOKEN", "secret"); }
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join(".mcp.json");
+        let mut cfg = sample_config(path.to_str().unwrap());
+        let env_cfg = cfg.environments.get_mut("env1").unwrap();
+        env_cfg.expand_secrets = Some(false);
+        assert!(write_target(&cfg.environments["env1"], HashMap::new(), &VarContext::default(), None).unwrap_err().to_string().contains("expandSecrets"));
+        cfg.environments.get_mut("env1").unwrap().mode = Some("claude_code".to_string());
+        let warnings = write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["a".to_string()]), &VarContext::default(), None).unwrap();
+        assert!(warnings.is_empty());
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["mcpServers"]["a"]["env"]["TOKEN"], "${SYNC_TEST_TOKEN}");
+        let mut servers = enabled_server_defs(&cfg, &["a".to_string()]);
+        servers.get_mut("a").unwrap().env.insert("URL".to_string(), "${SYNC_TEST_UNSET_URL:?set the url}".to_string());
+        let err = write_target(&cfg.environments["env1"], servers, &VarContext::default(), None).unwrap_err().to_string();
+        assert!(err.contains("SYNC_TEST_UNSET_URL: set the url"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_target_warns_plaintext_secret_in_git_repo() {
+        unsafe { std::env::set_var("SYNC_TEST_TOKEN", "secret"); }
+        let tmp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp_dir.path().join(".git")).unwrap();
+        let path = tmp_dir.path().join("sub/claude_desktop_config.json");
+        let cfg = sample_config(path.to_str().unwrap());
+        let warnings = write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["a".to_string(), "b".to_string()]), &VarContext::default(), None).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("a.env.TOKEN"), "{}", warnings[0]);
+        let warnings = write_target(&cfg.environments["env1"], enabled_server_defs(&cfg, &["b".to_string()]), &VarContext::default(), None).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_plaintext_secrets_by_key() {
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            env: HashMap::from([
+                ("API_KEY".to_string(), "sk-live-literal".to_string()),
+                ("ROOT".to_string(), "/home/me/proj".to_string()),
+                ("GITHUB_TOKEN".to_string(), "${GITHUB_TOKEN}".to_string()),
+                ("EMPTY_SECRET".to_string(), String::new()),
+                ("DB_URL".to_string(), "postgres://u:p@db".to_string()),
+            ]),
+            headers: HashMap::from([("Authorization".to_string(), "Bearer ${API_TOKEN}".to_string())]),
+            secret_env: vec!["DB_URL".to_string()],
+            ..Default::default()
+        })]);
+        assert_eq!(plaintext_secrets(&servers), vec!["a.env.API_KEY", "a.env.DB_URL"]);
+    }
+
+    #[test]
+    fn test_write_target_errors() {
+        let mut cfg = sample_config("");
+        assert!(write_target(&cfg.environments["env1"], HashMap::new(), &VarContext::default(), None).unwrap_err().to_string().contains("configPath"));
+        cfg.environments.get_mut("env1").unwrap().mode = Some("notepad".to_string());
+        assert_eq!(write_target(&cfg.environments["env1"], HashMap::new(), &VarContext::default(), None).unwrap_err().to_string(), "unknown mode: notepad");
+        cfg.environments.get_mut("env1").unwrap().mode = None;
+        assert!(write_target(&cfg.environments["env1"], HashMap::new(), &VarContext::default(), None).is_ok());
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use super::*;
+
+/// Claude Code。projectが無ければ.mcp.json、あれば~/.claude.jsonのprojects.<project>.mcpServers
+pub struct ClaudeCode {
+    pub project: Option<String>,
+}
+
+impl TargetWriter for ClaudeCode {
+    fn default_path(&self, home: &Path, project: Option<&str>) -> Option<PathBuf> {
+        project.map(|_| home.join(".claude.json"))
+    }
+
+    // ~/.claude.jsonには他の状態も保存されているので常にマージする
+    fn always_merge(&self) -> bool {
+        self.project.is_some()
+    }
+
+    fn supports_env_refs(&self) -> bool {
+        true
+    }
+
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        match &self.project {
+            Some(project) => merge_json_key(existing, &["projects", project, "mcpServers"], servers_value(servers, typed_remote_value, &STDIO_FIELDS)?),
+            None => merge_json_key(existing, &["mcpServers"], servers_value(servers, typed_remote_value, &STDIO_FIELDS)?),
+        }
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        let root = parse_json(content)?;
+        match &self.project {
+            Some(project) => servers_from_value(root.get("projects").and_then(|p| p.get(project)).and_then(|p| p.get("mcpServers")), Transport::Http),
+            None => servers_from_value(root.get("mcpServers"), Transport::Http),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_section_only() {
+        let writer = ClaudeCode { project: Some("/work/a".to_string()) };
+        let existing = r#"{"numStartups": 3, "projects": {"/work/a": {"allowedTools": [], "mcpServers": {}}, "/work/b": {"mcpServers": {"x": {"command": "x", "args": [], "env": {}}}}}}"#;
+        let servers = HashMap::from([("b".to_string(), McpServerConfig { command: "cat".to_string(), ..Default::default() })]);
+        let out = writer.merge(existing, &servers).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["numStartups"], 3);
+        assert!(value["projects"]["/work/a"]["allowedTools"].is_array());
+        assert_eq!(value["projects"]["/work/a"]["mcpServers"]["b"]["command"], "cat");
+        assert_eq!(value["projects"]["/work/b"]["mcpServers"]["x"]["command"], "x");
+        assert_eq!(writer.read_back(&out).unwrap().keys().collect::<Vec<_>>(), vec!["b"]);
+        assert_eq!(writer.default_path(Path::new("/h"), Some("/work/a")), Some(PathBuf::from("/h/.claude.json")));
+    }
+}
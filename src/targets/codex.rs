@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use super::*;
+
+/// Codex CLIの~/.codex/config.toml（[mcp_servers.<name>]テーブル）
+pub struct Codex;
+
+fn inline_table(values: &HashMap<String, String>) -> toml_edit::InlineTable {
+    let mut table = toml_edit::InlineTable::new();
+    for key in sorted_keys(values) {
+        table.insert(key, values[key].as_str().into());
+    }
+    table
+}
+
+fn string_map(item: Option<&toml_edit::Item>) -> HashMap<String, String> {
+    item.and_then(|v| v.as_table_like())
+        .map(|t| t.iter().filter_map(|(k, v)| v.as_str().map(|s| (k.to_string(), s.to_string()))).collect())
+        .unwrap_or_default()
+}
+
+/// 生成するキー（読み戻し時にextraへ入れない）
+const CODEX_KEYS: [&str; 6] = ["command", "args", "env", "url", "http_headers", "cwd"];
+
+/// JSONの値をTOMLの値に変換する（nullはTOMLで表せないのでNone）
+fn json_to_toml(value: &serde_json::Value) -> Option<toml_edit::Value> {
+    use serde_json::Value;
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some((*b).into()),
+        Value::Number(n) => n.as_i64().map(|i| i.into()).or_else(|| n.as_f64().map(|f| f.into())),
+        Value::String(s) => Some(s.as_str().into()),
+        Value::Array(items) => Some(toml_edit::Value::Array(items.iter().filter_map(json_to_toml).collect())),
+        Value::Object(map) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (k, v) in map {
+                if let Some(v) = json_to_toml(v) {
+                    table.insert(k, v);
+                }
+            }
+            Some(toml_edit::Value::InlineTable(table))
+        },
+    }
+}
+
+/// TOMLの値をJSONの値に変換する
+fn toml_to_json(item: &toml_edit::Item) -> Option<serde_json::Value> {
+    use serde_json::Value;
+    if let Some(table) = item.as_table_like() {
+        return Some(Value::Object(table.iter().filter_map(|(k, v)| toml_to_json(v).map(|v| (k.to_string(), v))).collect()));
+    }
+    match item.as_value()? {
+        toml_edit::Value::String(s) => Some(Value::String(s.value().clone())),
+        toml_edit::Value::Integer(i) => Some((*i.value()).into()),
+        toml_edit::Value::Float(f) => Some((*f.value()).into()),
+        toml_edit::Value::Boolean(b) => Some((*b.value()).into()),
+        toml_edit::Value::Datetime(d) => Some(Value::String(d.value().to_string())),
+        toml_edit::Value::Array(items) => Some(Value::Array(items.iter().filter_map(|v| toml_to_json(&toml_edit::Item::Value(v.clone()))).collect())),
+        toml_edit::Value::InlineTable(_) => None,
+    }
+}
+
+impl TargetWriter for Codex {
+    fn default_path(&self, home: &Path, _project: Option<&str>) -> Option<PathBuf> {
+        Some(home.join(".codex/config.toml"))
+    }
+
+    // [mcp_servers]だけを差し替える（他の設定とコメントは保持）
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        let mut doc = existing.parse::<toml_edit::DocumentMut>()?;
+        let mut mcp_servers = toml_edit::Table::new();
+        mcp_servers.set_implicit(true);
+        for name in sorted_keys(servers) {
+            let server = &servers[name];
+            let mut table = toml_edit::Table::new();
+            match server.transport {
+                Transport::Stdio => {
+                    table.insert("command", toml_edit::value(server.command.as_str()));
+                    table.insert("args", toml_edit::value(server.args.iter().collect::<toml_edit::Array>()));
+                    if !server.env.is_empty() {
+                        table.insert("env", toml_edit::value(inline_table(&server.env)));
+                    }
+                },
+                // Codexはstreamable HTTPのみ対応
+                Transport::Http => {
+                    table.insert("url", toml_edit::value(remote_url(name, server)?));
+                    if !server.headers.is_empty() {
+                        table.insert("http_headers", toml_edit::value(inline_table(&server.headers)));
+                    }
+                },
+                Transport::Sse => return Err(unsupported_transport("codex", name, server)),
+            }
+            if let Some(cwd) = &server.cwd {
+                table.insert("cwd", toml_edit::value(cwd.as_str()));
+            }
+            for (key, value) in &server.extra {
+                if !table.contains_key(key)
+                    && let Some(value) = json_to_toml(value) {
+                    table.insert(key, toml_edit::value(value));
+                }
+            }
+            mcp_servers.insert(name, toml_edit::Item::Table(table));
+        }
+        doc.insert("mcp_servers", toml_edit::Item::Table(mcp_servers));
+        Ok(doc.to_string())
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        let doc = content.parse::<toml_edit::DocumentMut>()?;
+        let mut servers = HashMap::new();
+        let Some(tables) = doc.get("mcp_servers").and_then(|t| t.as_table_like()) else {
+            return Ok(servers);
+        };
+        for (name, item) in tables.iter() {
+            let Some(table) = item.as_table_like() else { continue };
+            let command = table.get("command").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            let args = table.get("args").and_then(|v| v.as_array())
+                .map(|a| a.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+                .unwrap_or_default();
+            let url = table.get("url").and_then(|v| v.as_str()).map(|s| s.to_string());
+            let transport = if url.is_some() { Transport::Http } else { Transport::Stdio };
+            let extra = table.iter()
+                .filter(|(k, _)| !CODEX_KEYS.contains(k))
+                .filter_map(|(k, v)| toml_to_json(v).map(|v| (k.to_string(), v)))
+                .collect();
+            servers.insert(name.to_string(), McpServerConfig {
+                command,
+                args,
+                env: string_map(table.get("env")),
+                transport,
+                url,
+                headers: string_map(table.get("http_headers")),
+                cwd: table.get("cwd").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                extra,
+                ..Default::default()
+            });
+        }
+        Ok(servers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_keeps_settings_and_comments() {
+        let existing = "# my settings\nmodel = \"o3\"\n\n[mcp_servers.old]\ncommand = \"old\"\n\n[profiles.fast]\nmodel = \"mini\" # fast one\n";
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "echo".to_string(),
+            args: vec!["hi".to_string()],
+            env: HashMap::from([("TOKEN".to_string(), "t".to_string())]),
+            ..Default::default()
+        })]);
+        let out = Codex.merge(existing, &servers).unwrap();
+        assert!(out.contains("# my settings"));
+        assert!(out.contains("model = \"mini\" # fast one"));
+        assert!(!out.contains("[mcp_servers.old]"));
+        assert!(out.contains("[mcp_servers.a]\ncommand = \"echo\"\nargs = [\"hi\"]\nenv = { TOKEN = \"t\" }\n"));
+        let doc = out.parse::<toml_edit::DocumentMut>().unwrap();
+        assert_eq!(doc["model"].as_str(), Some("o3"));
+    }
+}
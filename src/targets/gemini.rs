@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use crate::jsonc;
+use super::*;
+
+/// Gemini CLIのsettings.json（mcpServers。cwd/timeout/trustも出力する）
+/// コメントを許す形式なのでJSONCとして扱う
+pub struct Gemini;
+
+/// Gemini CLIのstdioエントリはcwdに加えてtimeoutとtrustを持つ
+const GEMINI_STDIO_FIELDS: [&str; 3] = ["cwd", "timeout", "trust"];
+
+/// Gemini CLIはsseをurl、streamable HTTPをhttpUrlで表す
+fn gemini_remote_value(name: &str, server: &McpServerConfig) -> Result<Value, Box<dyn std::error::Error>> {
+    let url_key = if server.transport == Transport::Http { "httpUrl" } else { "url" };
+    let mut map = remote_value(url_key, name, server)?;
+    if let Some(timeout) = server.timeout {
+        map.insert("timeout".to_string(), timeout.into());
+    }
+    if let Some(trust) = server.trust {
+        map.insert("trust".to_string(), trust.into());
+    }
+    Ok(Value::Object(map))
+}
+
+impl TargetWriter for Gemini {
+    fn default_path(&self, home: &Path, project: Option<&str>) -> Option<PathBuf> {
+        match project {
+            Some(project) => Some(PathBuf::from(project).join(".gemini/settings.json")),
+            None => Some(home.join(".gemini/settings.json")),
+        }
+    }
+
+    fn supports_env_refs(&self) -> bool {
+        true
+    }
+
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        jsonc::parse(existing)?;
+        jsonc::set_top_level_key(existing, "mcpServers", &servers_value(servers, gemini_remote_value, &GEMINI_STDIO_FIELDS)?)
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        servers_from_value(jsonc::parse(content)?.get("mcpServers"), Transport::Sse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_gemini_fields() {
+        let servers = HashMap::from([("b".to_string(), McpServerConfig {
+            command: "cat".to_string(),
+            cwd: Some("/work".to_string()),
+            timeout: Some(30000),
+            trust: Some(true),
+            ..Default::default()
+        })]);
+        let out = Gemini.merge("{\n  \"theme\": \"GitHub\"\n}\n", &servers).unwrap();
+        let value = jsonc::parse(&out).unwrap();
+        assert_eq!(value["theme"], "GitHub");
+        assert_eq!(value["mcpServers"]["b"]["cwd"], "/work");
+        assert_eq!(value["mcpServers"]["b"]["timeout"], 30000);
+        assert_eq!(value["mcpServers"]["b"]["trust"], true);
+        assert_eq!(Gemini.read_back(&out).unwrap()["b"].trust, Some(true));
+    }
+}
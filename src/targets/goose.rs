@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde_norway::{Mapping, Value};
+use super::*;
+
+/// Gooseの~/.config/goose/config.yaml（extensions）
+/// builtinの拡張はMCPalleteの管理対象外なので残す。YAMLのコメントは保持されない
+pub struct Goose;
+
+/// Gooseのextensions形式に変換する（timeoutは秒単位）
+pub fn goose_extensions(servers: &HashMap<String, McpServerConfig>) -> Result<Mapping, Box<dyn std::error::Error>> {
+    let mut extensions = Mapping::new();
+    for name in sorted_keys(servers) {
+        let server = &servers[name];
+        let mut ext = Mapping::new();
+        ext.insert("name".into(), name.as_str().into());
+        match server.transport {
+            Transport::Stdio => {
+                ext.insert("type".into(), "stdio".into());
+                ext.insert("enabled".into(), true.into());
+                ext.insert("cmd".into(), server.command.as_str().into());
+                ext.insert("args".into(), server.args.iter().map(|a| Value::from(a.as_str())).collect::<Vec<_>>().into());
+            },
+            Transport::Sse | Transport::Http => {
+                let kind = if server.transport == Transport::Sse { "sse" } else { "streamable_http" };
+                ext.insert("type".into(), kind.into());
+                ext.insert("enabled".into(), true.into());
+                ext.insert("uri".into(), remote_url(name, server)?.into());
+                if !server.headers.is_empty() {
+                    ext.insert("headers".into(), Value::Mapping(string_mapping(&server.headers)));
+                }
+            },
+        }
+        ext.insert("envs".into(), Value::Mapping(string_mapping(&server.env)));
+        if let Some(timeout) = server.timeout {
+            ext.insert("timeout".into(), timeout.div_ceil(1000).into());
+        }
+        for (key, value) in &server.extra {
+            if !ext.contains_key(key.as_str()) {
+                ext.insert(key.as_str().into(), serde_norway::to_value(value)?);
+            }
+        }
+        extensions.insert(name.as_str().into(), Value::Mapping(ext));
+    }
+    Ok(extensions)
+}
+
+fn string_mapping(values: &HashMap<String, String>) -> Mapping {
+    sorted_keys(values).into_iter()
+        .map(|k| (k.as_str().into(), values[k].as_str().into()))
+        .collect()
+}
+
+fn parse_yaml(s: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    if s.trim().is_empty() {
+        return Ok(Value::Mapping(Mapping::new()));
+    }
+    Ok(serde_norway::from_str(s)?)
+}
+
+/// 生成するキー（読み戻し時にextraへ入れない）
+const GOOSE_KEYS: [&str; 9] = ["name", "type", "enabled", "cmd", "args", "envs", "uri", "headers", "timeout"];
+
+fn is_builtin(ext: &Value) -> bool {
+    ext.get("type").and_then(|t| t.as_str()) == Some("builtin")
+}
+
+impl TargetWriter for Goose {
+    fn default_path(&self, home: &Path, _project: Option<&str>) -> Option<PathBuf> {
+        Some(home.join(".config/goose/config.yaml"))
+    }
+
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        let mut root = parse_yaml(existing)?;
+        let root_map = root.as_mapping_mut().ok_or("target config is not a YAML mapping")?;
+        let mut extensions = Mapping::new();
+        if let Some(Value::Mapping(old)) = root_map.get("extensions") {
+            for (name, ext) in old {
+                if is_builtin(ext) {
+                    extensions.insert(name.clone(), ext.clone());
+                }
+            }
+        }
+        extensions.extend(goose_extensions(servers)?);
+        root_map.insert("extensions".into(), Value::Mapping(extensions));
+        Ok(serde_norway::to_string(&root)?)
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        let root = parse_yaml(content)?;
+        let mut servers = HashMap::new();
+        if let Some(Value::Mapping(extensions)) = root.get("extensions") {
+            for (name, ext) in extensions {
+                let Some(name) = name.as_str() else { continue };
+                if is_builtin(ext) {
+                    continue;
+                }
+                let transport = match ext.get("type").and_then(|t| t.as_str()) {
+                    Some("sse") => Transport::Sse,
+                    Some("streamable_http") => Transport::Http,
+                    _ => Transport::Stdio,
+                };
+                let server = McpServerConfig {
+                    command: ext.get("cmd").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                    args: serde_norway::from_value(ext.get("args").cloned().unwrap_or(Value::Sequence(vec![])))?,
+                    env: serde_norway::from_value(ext.get("envs").cloned().unwrap_or(Value::Mapping(Mapping::new())))?,
+                    transport,
+                    url: ext.get("uri").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                    headers: serde_norway::from_value(ext.get("headers").cloned().unwrap_or(Value::Mapping(Mapping::new())))?,
+                    timeout: ext.get("timeout").and_then(|v| v.as_u64()).map(|t| t * 1000),
+                    extra: ext.as_mapping().into_iter().flatten()
+                        .filter_map(|(k, v)| Some((k.as_str()?, v)))
+                        .filter(|(k, _)| !GOOSE_KEYS.contains(k))
+                        .map(|(k, v)| Ok((k.to_string(), serde_json::to_value(v)?)))
+                        .collect::<Result<_, Box<dyn std::error::Error>>>()?,
+                    ..Default::default()
+                };
+                servers.insert(name.to_string(), server);
+            }
+        }
+        Ok(servers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_keeps_builtins() {
+        let existing = "GOOSE_PROVIDER: openai\nextensions:\n  developer:\n    enabled: true\n    name: developer\n    type: builtin\n  old:\n    cmd: old\n    type: stdio\n";
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "echo".to_string(),
+            env: HashMap::from([("TOKEN".to_string(), "t".to_string())]),
+            timeout: Some(1500),
+            ..Default::default()
+        })]);
+        let out = Goose.merge(existing, &servers).unwrap();
+        let value: Value = serde_norway::from_str(&out).unwrap();
+        assert_eq!(value["GOOSE_PROVIDER"].as_str(), Some("openai"));
+        assert_eq!(value["extensions"]["developer"]["type"].as_str(), Some("builtin"));
+        assert!(value["extensions"].get("old").is_none());
+        assert_eq!(value["extensions"]["a"]["cmd"].as_str(), Some("echo"));
+        assert_eq!(value["extensions"]["a"]["envs"]["TOKEN"].as_str(), Some("t"));
+        assert_eq!(value["extensions"]["a"]["timeout"].as_u64(), Some(2));
+        let back = Goose.read_back(&out).unwrap();
+        assert_eq!(back.keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+}
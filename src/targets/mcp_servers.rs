@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use super::*;
+
+// mcpServersキーを持つJSONを使うクライアント
+
+fn merge_mcp_servers(existing: &str, servers: &HashMap<String, McpServerConfig>, remote: RemoteRenderer) -> Result<String, Box<dyn std::error::Error>> {
+    merge_json_key(existing, &["mcpServers"], servers_value(servers, remote, &STDIO_FIELDS)?)
+}
+
+fn read_mcp_servers(content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+    servers_from_value(parse_json(content)?.get("mcpServers"), Transport::Http)
+}
+
+pub struct ClaudeDesktop;
+
+impl TargetWriter for ClaudeDesktop {
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        merge_mcp_servers(existing, servers, |name, server| Err(unsupported_transport("claude_desktop", name, server)))
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        read_mcp_servers(content)
+    }
+}
+
+pub struct Cursor;
+
+impl TargetWriter for Cursor {
+    fn default_path(&self, home: &Path, project: Option<&str>) -> Option<PathBuf> {
+        match project {
+            Some(project) => Some(PathBuf::from(project).join(".cursor/mcp.json")),
+            None => Some(home.join(".cursor/mcp.json")),
+        }
+    }
+
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        merge_mcp_servers(existing, servers, typed_remote_value)
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        read_mcp_servers(content)
+    }
+}
+
+pub struct Windsurf;
+
+impl TargetWriter for Windsurf {
+    fn default_path(&self, home: &Path, _project: Option<&str>) -> Option<PathBuf> {
+        Some(home.join(".codeium/windsurf/mcp_config.json"))
+    }
+
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        merge_mcp_servers(existing, servers, |name, server| Ok(Value::Object(remote_value("serverUrl", name, server)?)))
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        read_mcp_servers(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_paths() {
+        let home = Path::new("/home/tester");
+        assert_eq!(Cursor.default_path(home, None), Some(PathBuf::from("/home/tester/.cursor/mcp.json")));
+        assert_eq!(Cursor.default_path(home, Some("/work/a")), Some(PathBuf::from("/work/a/.cursor/mcp.json")));
+        assert_eq!(Windsurf.default_path(home, Some("/work/a")), Some(PathBuf::from("/home/tester/.codeium/windsurf/mcp_config.json")));
+        assert_eq!(ClaudeDesktop.default_path(home, None), None);
+    }
+
+    #[test]
+    fn test_merge_keeps_other_keys() {
+        let existing = r#"{"globalShortcut": "Alt+Space", "mcpServers": {"old": {"command": "x", "args": [], "env": {}}}}"#;
+        let servers = HashMap::from([("b".to_string(), McpServerConfig { command: "cat".to_string(), ..Default::default() })]);
+        let out = ClaudeDesktop.merge(existing, &servers).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["globalShortcut"], "Alt+Space");
+        let back = ClaudeDesktop.read_back(&out).unwrap();
+        assert_eq!(back.keys().collect::<Vec<_>>(), vec!["b"]);
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
+use crate::model::*;
+
+mod claude_code;
+mod codex;
+mod gemini;
+mod goose;
+mod mcp_servers;
+mod vscode;
+mod zed;
+
+pub use claude_code::ClaudeCode;
+pub use codex::Codex;
+pub use gemini::Gemini;
+pub use goose::Goose;
+pub use mcp_servers::{ClaudeDesktop, Cursor, Windsurf};
+pub use vscode::VsCode;
+pub use zed::Zed;
+
+/// クライアントごとの設定ファイル形式
+pub trait TargetWriter {
+    /// configPathが空の場合の既定パス
+    fn default_path(&self, _home: &Path, _project: Option<&str>) -> Option<PathBuf> {
+        None
+    }
+
+    /// env・headersの変数参照をwriterがクライアントの形式に変換するか
+    /// trueの場合、${cmd:...} / ${file:...}だけを解決し、他の参照は書かれたまま渡す
+    fn converts_env_refs(&self) -> bool {
+        false
+    }
+
+    /// クライアントがenv・headersの${VAR}参照を自分で解決できるか（expandSecrets: falseを使える）
+    fn supports_env_refs(&self) -> bool {
+        false
+    }
+
+    /// merge: falseでも既存ファイルを丸ごと置き換えてはいけない形式か
+    fn always_merge(&self) -> bool {
+        false
+    }
+
+    /// サーバ定義から新しいファイルの内容を作る
+    fn render(&self, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        self.merge("", servers)
+    }
+
+    /// 既存ファイルの内容のうちサーバ定義の部分だけを差し替える
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// ファイルの内容からサーバ定義を読み戻す
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>>;
+}
+
+/// modeに対応するTargetWriterを返す
+pub fn writer_for(mode: &str, project: Option<&str>) -> Result<Box<dyn TargetWriter>, Box<dyn std::error::Error>> {
+    let writer: Box<dyn TargetWriter> = match mode {
+        "claude_desktop" => Box::new(ClaudeDesktop),
+        "claude_code" => Box::new(ClaudeCode { project: project.map(|p| p.to_string()) }),
+        "cursor" => Box::new(Cursor),
+        "windsurf" => Box::new(Windsurf),
+        "vscode" => Box::new(VsCode),
+        "codex" => Box::new(Codex),
+        "zed" => Box::new(Zed),
+        "gemini" => Box::new(Gemini),
+        "goose" => Box::new(Goose),
+        _ => return Err(format!("unknown mode: {}", mode).into()),
+    };
+    Ok(writer)
+}
+
+/// 名前順に並べたキー
+pub fn sorted_keys<V>(map: &HashMap<String, V>) -> Vec<&String> {
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+    keys
+}
+
+/// sse / httpのサーバのエントリを作る関数（クライアントごとに形が異なる）
+pub type RemoteRenderer = fn(&str, &McpServerConfig) -> Result<Value, Box<dyn std::error::Error>>;
+
+/// 読み戻し時にextraへ入れないキー（各クライアントのtransport表現）
+const TRANSPORT_KEYS: [&str; 3] = ["type", "serverUrl", "httpUrl"];
+
+/// mcpServers形式のstdioエントリに出力する任意フィールド（command・args・envは常に出力する）
+/// timeoutとtrustはGemini CLIだけが使うので、Gemini以外では出力しない
+pub const STDIO_FIELDS: [&str; 1] = ["cwd"];
+
+/// stdioのサーバのエントリ。fieldsに含まれる任意フィールドは値がある場合だけ出力する
+fn stdio_value(server: &McpServerConfig, fields: &[&str]) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("command".to_string(), Value::String(server.command.clone()));
+    map.insert("args".to_string(), serde_json::json!(server.args));
+    // HashMapの順序は実行ごとに変わるので、envは名前順で出力する
+    map.insert("env".to_string(), Value::Object(env_value(&server.env)));
+    for field in fields {
+        let value = match *field {
+            "cwd" => server.cwd.clone().map(Value::String),
+            "timeout" => server.timeout.map(Value::from),
+            "trust" => server.trust.map(Value::Bool),
+            _ => None,
+        };
+        if let Some(value) = value {
+            map.insert(field.to_string(), value);
+        }
+    }
+    map
+}
+
+/// サーバ定義を名前順に並べたmcpServersの値を作る。stdio以外はremoteで変換する
+/// fieldsはstdioエントリに出力する任意フィールド（STDIO_FIELDSなど）
+pub fn servers_value(servers: &HashMap<String, McpServerConfig>, remote: RemoteRenderer, fields: &[&str]) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut map = Map::new();
+    for name in sorted_keys(servers) {
+        let server = &servers[name];
+        let mut value = if server.transport.is_stdio() {
+            Value::Object(stdio_value(server, fields))
+        } else {
+            remote(name, server)?
+        };
+        if let Value::Object(entry) = &mut value {
+            with_extra(entry, server);
+        }
+        map.insert(name.clone(), value);
+    }
+    Ok(Value::Object(map))
+}
+
+/// extraのキーをエントリへ追加する（MCPalleteが生成するキーが優先）
+pub fn with_extra(entry: &mut Map<String, Value>, server: &McpServerConfig) {
+    for (key, value) in &server.extra {
+        entry.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+}
+
+/// クライアントが対応していないtransportのエラー
+pub fn unsupported_transport(mode: &str, name: &str, server: &McpServerConfig) -> Box<dyn std::error::Error> {
+    format!("{} does not support {} transport (server {})", mode, server.transport.as_str(), name).into()
+}
+
+/// リモートサーバの接続先
+pub fn remote_url<'a>(name: &str, server: &'a McpServerConfig) -> Result<&'a str, Box<dyn std::error::Error>> {
+    server.url.as_deref()
+        .ok_or_else(|| format!("url is required for {} transport (server {})", server.transport.as_str(), name).into())
+}
+
+/// url_keyに接続先、headersにヘッダを持つエントリを作る
+pub fn remote_value(url_key: &str, name: &str, server: &McpServerConfig) -> Result<Map<String, Value>, Box<dyn std::error::Error>> {
+    let mut map = Map::new();
+    map.insert(url_key.to_string(), Value::String(remote_url(name, server)?.to_string()));
+    if !server.headers.is_empty() {
+        map.insert("headers".to_string(), Value::Object(env_value(&server.headers)));
+    }
+    Ok(map)
+}
+
+/// typeキーを付けたurl + headersのエントリ（Claude Code / VS Codeなど）
+pub fn typed_remote_value(name: &str, server: &McpServerConfig) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut map = Map::new();
+    map.insert("type".to_string(), Value::String(server.transport.as_str().to_string()));
+    map.extend(remote_value("url", name, server)?);
+    Ok(Value::Object(map))
+}
+
+/// mcpServers形式の値からサーバ定義を読み戻す（値が無ければ空）
+/// url系のキーを持つエントリはリモートとして扱い、typeが無い場合はuntyped_urlとみなす
+pub fn servers_from_value(value: Option<&Value>, untyped_url: Transport) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+    let mut servers = HashMap::new();
+    if let Some(Value::Object(map)) = value {
+        for (name, v) in map {
+            let mut server: McpServerConfig = serde_json::from_value(v.clone())?;
+            server.extra.retain(|k, _| !TRANSPORT_KEYS.contains(&k.as_str()));
+            if let Some(key) = ["url", "serverUrl", "httpUrl"].into_iter().find(|k| v.get(*k).is_some_and(|u| u.is_string())) {
+                server.url = v[key].as_str().map(|u| u.to_string());
+                server.transport = match v.get("type").and_then(|t| t.as_str()) {
+                    Some("sse") => Transport::Sse,
+                    Some("http") | Some("streamable-http") | Some("streamableHttp") => Transport::Http,
+                    _ if key == "httpUrl" => Transport::Http,
+                    _ => untyped_url,
+                };
+            }
+            servers.insert(name.clone(), server);
+        }
+    }
+    Ok(servers)
+}
+
+/// envを名前順に並べたJSONオブジェクトにする
+pub fn env_value(env: &HashMap<String, String>) -> Map<String, Value> {
+    sorted_keys(env).into_iter()
+        .map(|k| (k.clone(), Value::String(env[k].clone())))
+        .collect()
+}
+
+/// 既存のJSONを型なしで読み込み、キーパスの値だけを差し替える（他のキーとその順序は保持）
+/// 途中のオブジェクトが無ければ作成する
+pub fn merge_json_key(existing: &str, key_path: &[&str], value: Value) -> Result<String, Box<dyn std::error::Error>> {
+    let mut root = parse_json(existing)?;
+    let (last, parents) = key_path.split_last().ok_or("empty key path")?;
+    let mut obj = root.as_object_mut().ok_or("target config is not a JSON object")?;
+    for key in parents {
+        obj = obj.entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .ok_or_else(|| format!("\"{}\" in target config is not a JSON object", key))?;
+    }
+    obj.insert(last.to_string(), value);
+    Ok(serde_json::to_string_pretty(&root)?)
+}
+
+/// JSONとして読み込む（空文字列は空オブジェクト扱い）
+pub fn parse_json(s: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    if s.trim().is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    Ok(serde_json::from_str(s)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_json_key_keeps_other_keys() {
+        let existing = r#"{"globalShortcut": "Alt+Space", "mcpServers": {"old": {}}, "preferences": {"x": 1}}"#;
+        let servers = serde_json::json!({"new": {"command": "echo"}});
+        let merged = merge_json_key(existing, &["mcpServers"], servers).unwrap();
+        let value: Value = serde_json::from_str(&merged).unwrap();
+        let keys: Vec<&String> = value.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["globalShortcut", "mcpServers", "preferences"]);
+        assert!(value["mcpServers"].get("old").is_none());
+        assert_eq!(value["mcpServers"]["new"]["command"], "echo");
+        assert!(merge_json_key("[]", &["mcpServers"], Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_writer_for_unknown_mode() {
+        assert!(writer_for("claude_desktop", None).is_ok());
+        let err = writer_for("notepad", None).err().unwrap();
+        assert_eq!(err.to_string(), "unknown mode: notepad");
+    }
+
+    #[test]
+    fn test_every_writer_round_trips() {
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "npx".to_string(),
+            args: vec!["-y".to_string(), "pkg".to_string()],
+            env: HashMap::from([("KEY".to_string(), "value".to_string())]),
+            ..Default::default()
+        })]);
+        for mode in ["claude_desktop", "claude_code", "cursor", "windsurf", "vscode", "codex", "zed", "gemini", "goose"] {
+            let writer = writer_for(mode, None).unwrap();
+            let rendered = writer.render(&servers).unwrap();
+            let back = writer.read_back(&rendered).unwrap();
+            assert_eq!(back["a"].command, "npx", "{}", mode);
+            assert_eq!(back["a"].args, servers["a"].args, "{}", mode);
+            assert_eq!(back["a"].env, servers["a"].env, "{}", mode);
+            assert!(writer.read_back("").unwrap().is_empty(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        // HashMapは作るたびにキーの順序が変わるので、同じ内容を作り直して比べる
+        let servers = || HashMap::from([("a".to_string(), McpServerConfig {
+            command: "npx".to_string(),
+            env: (0..6).map(|i| (format!("KEY_{}", i), i.to_string())).collect(),
+            ..Default::default()
+        })]);
+        for mode in ["claude_desktop", "claude_code", "cursor", "windsurf", "vscode", "codex", "zed", "gemini", "goose"] {
+            let writer = writer_for(mode, None).unwrap();
+            let first = writer.render(&servers()).unwrap();
+            for _ in 0..4 {
+                assert_eq!(first.as_bytes(), writer.render(&servers()).unwrap().as_bytes(), "{}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemini_fields_only_in_gemini() {
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "npx".to_string(),
+            cwd: Some("/work".to_string()),
+            timeout: Some(30000),
+            trust: Some(true),
+            ..Default::default()
+        })]);
+        for mode in ["claude_desktop", "claude_code", "cursor", "windsurf"] {
+            let rendered = writer_for(mode, None).unwrap().render(&servers).unwrap();
+            let value: Value = serde_json::from_str(&rendered).unwrap();
+            let entry = value["mcpServers"]["a"].as_object().unwrap();
+            let keys: Vec<&String> = entry.keys().collect();
+            assert_eq!(keys, vec!["command", "args", "env", "cwd"], "{}", mode);
+        }
+        let back = Gemini.read_back(&Gemini.render(&servers).unwrap()).unwrap();
+        assert_eq!((back["a"].timeout, back["a"].trust), (Some(30000), Some(true)));
+    }
+
+    #[test]
+    fn test_extra_fields_round_trip() {
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "npx".to_string(),
+            description: Some("internal".to_string()),
+            tags: vec!["t".to_string()],
+            extra: serde_json::json!({"alwaysAllow": ["read"], "disabled": false}).as_object().unwrap().clone(),
+            ..Default::default()
+        })]);
+        for mode in ["claude_desktop", "claude_code", "cursor", "windsurf", "vscode", "codex", "zed", "gemini", "goose"] {
+            let writer = writer_for(mode, None).unwrap();
+            let rendered = writer.render(&servers).unwrap();
+            assert!(!rendered.contains("internal"), "{}", mode);
+            let back = writer.read_back(&rendered).unwrap();
+            assert_eq!(back["a"].extra, servers["a"].extra, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn test_remote_round_trips_or_is_unsupported() {
+        let servers = HashMap::from([("r".to_string(), McpServerConfig {
+            transport: Transport::Http,
+            url: Some("https://example.com/mcp".to_string()),
+            headers: HashMap::from([("Authorization".to_string(), "Bearer t".to_string())]),
+            ..Default::default()
+        })]);
+        for mode in ["claude_code", "cursor", "windsurf", "vscode", "codex", "gemini", "goose"] {
+            let writer = writer_for(mode, None).unwrap();
+            let back = writer.read_back(&writer.render(&servers).unwrap()).unwrap();
+            assert_eq!(back["r"].transport, Transport::Http, "{}", mode);
+            assert_eq!(back["r"].url.as_deref(), Some("https://example.com/mcp"), "{}", mode);
+            assert_eq!(back["r"].headers, servers["r"].headers, "{}", mode);
+        }
+        for mode in ["claude_desktop", "zed"] {
+            let err = writer_for(mode, None).unwrap().render(&servers).unwrap_err();
+            assert_eq!(err.to_string(), format!("{} does not support http transport (server r)", mode));
+        }
+        let mut sse = servers.clone();
+        sse.get_mut("r").unwrap().transport = Transport::Sse;
+        assert!(writer_for("codex", None).unwrap().render(&sse).is_err());
+        for mode in ["claude_code", "vscode", "gemini", "goose"] {
+            let writer = writer_for(mode, None).unwrap();
+            assert_eq!(writer.read_back(&writer.render(&sse).unwrap()).unwrap()["r"].transport, Transport::Sse, "{}", mode);
+        }
+        sse.get_mut("r").unwrap().url = None;
+        assert!(writer_for("claude_code", None).unwrap().render(&sse).unwrap_err().to_string().starts_with("url is required"));
+    }
+}
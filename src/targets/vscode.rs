@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
+use crate::config::*;
+use crate::jsonc;
+use super::*;
+
+/// VS Codeの.vscode/mcp.json（servers + inputs）
+/// ワークスペースにコミットされるため、envの$VAR参照は展開せず${input:VAR}に置き換える
+/// ${VAR:-default}の既定値はinputのdefaultにする
+pub struct VsCode;
+
+/// VS Code形式（servers + inputs）に変換する
+pub fn vscode_value(servers: &HashMap<String, McpServerConfig>) -> Result<(Value, Vec<Value>), Box<dyn std::error::Error>> {
+    // inputのid -> 既定値（同じ変数は最初に見つかった既定値を使う）
+    let mut input_defaults: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut map = Map::new();
+    // 値の中の$VAR参照を${input:VAR}に置き換え、使われた変数をinputとして集める
+    let mut to_inputs = |values: &HashMap<String, String>| -> Result<Map<String, Value>, Box<dyn std::error::Error>> {
+        let mut out = Map::new();
+        for key in sorted_keys(values) {
+            let value = replace_env_vars(&values[key], |var, default| {
+                let input_default = input_defaults.entry(var.to_string()).or_default();
+                if input_default.is_none() {
+                    *input_default = default.map(|d| d.to_string());
+                }
+                format!("${{input:{}}}", var)
+            })?;
+            out.insert(key.clone(), Value::String(value));
+        }
+        Ok(out)
+    };
+    for name in sorted_keys(servers) {
+        let server = &servers[name];
+        let mut entry = if server.transport.is_stdio() {
+            serde_json::json!({
+                "type": "stdio",
+                "command": server.command,
+                "args": server.args,
+                "env": to_inputs(&server.env)?,
+            })
+        } else {
+            let mut entry = typed_remote_value(name, server)?;
+            if !server.headers.is_empty() {
+                entry["headers"] = Value::Object(to_inputs(&server.headers)?);
+            }
+            entry
+        };
+        if let Value::Object(entry) = &mut entry {
+            if let Some(cwd) = &server.cwd {
+                entry.insert("cwd".to_string(), Value::String(cwd.clone()));
+            }
+            with_extra(entry, server);
+        }
+        map.insert(name.clone(), entry);
+    }
+    let inputs = input_defaults.iter().map(|(id, default)| {
+        let mut input = serde_json::json!({
+            "type": "promptString",
+            "id": id,
+            "description": id,
+            "password": true,
+        });
+        if let Some(default) = default {
+            input["default"] = Value::String(default.clone());
+        }
+        input
+    }).collect();
+    Ok((Value::Object(map), inputs))
+}
+
+/// 値の中の文字列で使われている${input:ID}のidを集める
+fn referenced_inputs(value: &Value, ids: &mut BTreeSet<String>) {
+    match value {
+        Value::String(s) => {
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find("${input:") {
+                rest = &rest[start + "${input:".len()..];
+                let Some(end) = rest.find('}') else { break };
+                ids.insert(rest[..end].to_string());
+                rest = &rest[end + 1..];
+            }
+        },
+        Value::Array(values) => values.iter().for_each(|v| referenced_inputs(v, ids)),
+        Value::Object(map) => map.values().for_each(|v| referenced_inputs(v, ids)),
+        _ => {},
+    }
+}
+
+impl TargetWriter for VsCode {
+    fn default_path(&self, _home: &Path, project: Option<&str>) -> Option<PathBuf> {
+        project.map(|p| PathBuf::from(p).join(".vscode/mcp.json"))
+    }
+
+    // envとheadersの参照は${input:VAR}に変換する
+    fn converts_env_refs(&self) -> bool {
+        true
+    }
+
+    // JSONCなのでinputsとserversの値だけをテキスト上で差し替える
+    // 既存のinputsのうち、前回書き出したserversが参照していないもの（ユーザーが追加したもの）は残す
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        let root = jsonc::parse(existing)?;
+        let (servers, mut inputs) = vscode_value(servers)?;
+        if let Some(Value::Array(old_inputs)) = root.get("inputs") {
+            let mut managed = BTreeSet::new();
+            if let Some(old_servers) = root.get("servers") {
+                referenced_inputs(old_servers, &mut managed);
+            }
+            inputs.iter().filter_map(|i| i["id"].as_str()).for_each(|id| {
+                managed.insert(id.to_string());
+            });
+            let kept: Vec<Value> = old_inputs.iter()
+                .filter(|i| !i["id"].as_str().is_some_and(|id| managed.contains(id)))
+                .cloned()
+                .collect();
+            inputs = kept.into_iter().chain(inputs).collect();
+        }
+        let json = jsonc::set_top_level_key(existing, "inputs", &Value::Array(inputs))?;
+        jsonc::set_top_level_key(&json, "servers", &servers)
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        servers_from_value(jsonc::parse(content)?.get("servers"), Transport::Http)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers() -> HashMap<String, McpServerConfig> {
+        HashMap::from([("a".to_string(), McpServerConfig {
+            command: "echo".to_string(),
+            env: HashMap::from([("TOKEN".to_string(), "Bearer $VSCODE_TEST_TOKEN".to_string())]),
+            ..Default::default()
+        })])
+    }
+
+    #[test]
+    fn test_vscode_value_uses_inputs() {
+        let (servers, inputs) = vscode_value(&servers()).unwrap();
+        assert_eq!(servers["a"]["type"], "stdio");
+        assert_eq!(servers["a"]["env"]["TOKEN"], "Bearer ${input:VSCODE_TEST_TOKEN}");
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0]["id"], "VSCODE_TEST_TOKEN");
+        assert_eq!(inputs[0]["password"], true);
+        assert!(inputs[0].get("default").is_none());
+    }
+
+    #[test]
+    fn test_vscode_value_keeps_defaults() {
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "echo".to_string(),
+            env: HashMap::from([
+                ("MODE".to_string(), "${VSCODE_TEST_MODE:-dev}".to_string()),
+                ("PRICE".to_string(), "$$5".to_string()),
+            ]),
+            ..Default::default()
+        })]);
+        let (servers, inputs) = vscode_value(&servers).unwrap();
+        assert_eq!(servers["a"]["env"]["MODE"], "${input:VSCODE_TEST_MODE}");
+        assert_eq!(servers["a"]["env"]["PRICE"], "$5");
+        assert_eq!(inputs[0]["default"], "dev");
+    }
+
+    #[test]
+    fn test_merge_keeps_foreign_inputs() {
+        let existing = r#"{"inputs": [{"type": "promptString", "id": "other"}], "servers": {"old": {}}}"#;
+        let out = VsCode.merge(existing, &servers()).unwrap();
+        let value: Value = serde_json::from_str(&out).unwrap();
+        let ids: Vec<&str> = value["inputs"].as_array().unwrap().iter().map(|i| i["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["other", "VSCODE_TEST_TOKEN"]);
+        assert!(value["servers"].get("old").is_none());
+    }
+
+    #[test]
+    fn test_merge_drops_stale_inputs() {
+        let existing = r#"// workspace servers
+{
+  "inputs": [
+    {"type": "promptString", "id": "other"},
+    {"type": "promptString", "id": "GONE"}
+  ],
+  "servers": {"old": {"type": "stdio", "command": "cat", "env": {"KEY": "${input:GONE}"}}}
+}
+"#;
+        let out = VsCode.merge(existing, &servers()).unwrap();
+        assert!(out.starts_with("// workspace servers"));
+        let value = jsonc::parse(&out).unwrap();
+        let ids: Vec<&str> = value["inputs"].as_array().unwrap().iter().map(|i| i["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["other", "VSCODE_TEST_TOKEN"]);
+        assert_eq!(VsCode.read_back(&out).unwrap().keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+}
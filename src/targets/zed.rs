@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
+use crate::jsonc;
+use super::*;
+
+/// Zedのsettings.json（JSONC）のcontext_servers
+pub struct Zed;
+
+/// Zedの拡張機能が追加したエントリ（commandが無くsettingsだけのもの）。MCPalleteは管理しない
+fn is_extension(entry: &Value) -> bool {
+    entry.get("command").is_none()
+}
+
+/// Zedのcontext_servers形式（command: { path, args, env }）に変換する
+pub fn zed_value(servers: &HashMap<String, McpServerConfig>) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut map = Map::new();
+    for name in sorted_keys(servers) {
+        let server = &servers[name];
+        if !server.transport.is_stdio() {
+            return Err(unsupported_transport("zed", name, server));
+        }
+        let mut entry = serde_json::json!({
+            "command": {
+                "path": server.command,
+                "args": server.args,
+                "env": env_value(&server.env),
+            },
+            "settings": {},
+        });
+        if let Value::Object(entry) = &mut entry {
+            with_extra(entry, server);
+        }
+        map.insert(name.clone(), entry);
+    }
+    Ok(Value::Object(map))
+}
+
+impl TargetWriter for Zed {
+    fn default_path(&self, home: &Path, project: Option<&str>) -> Option<PathBuf> {
+        match project {
+            Some(project) => Some(PathBuf::from(project).join(".zed/settings.json")),
+            None => Some(home.join(".config/zed/settings.json")),
+        }
+    }
+
+    // JSONCなのでcontext_serversの値だけをテキスト上で差し替える。拡張機能のエントリは残す
+    fn merge(&self, existing: &str, servers: &HashMap<String, McpServerConfig>) -> Result<String, Box<dyn std::error::Error>> {
+        // 壊れたファイルを書き換えないよう先に読めることを確認する
+        let root = jsonc::parse(existing)?;
+        let mut context_servers = Map::new();
+        if let Some(Value::Object(old)) = root.get("context_servers") {
+            for (name, entry) in old {
+                if is_extension(entry) {
+                    context_servers.insert(name.clone(), entry.clone());
+                }
+            }
+        }
+        if let Value::Object(servers) = zed_value(servers)? {
+            context_servers.extend(servers);
+        }
+        jsonc::set_top_level_key(existing, "context_servers", &Value::Object(context_servers))
+    }
+
+    fn read_back(&self, content: &str) -> Result<HashMap<String, McpServerConfig>, Box<dyn std::error::Error>> {
+        let root = jsonc::parse(content)?;
+        let mut servers = HashMap::new();
+        if let Some(Value::Object(context_servers)) = root.get("context_servers") {
+            for (name, entry) in context_servers {
+                if is_extension(entry) {
+                    continue;
+                }
+                let command = &entry["command"];
+                let extra = entry.as_object().into_iter().flatten()
+                    .filter(|(k, _)| !["command", "settings"].contains(&k.as_str()))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                let server = McpServerConfig {
+                    command: command.get("path").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                    args: serde_json::from_value(command.get("args").cloned().unwrap_or(Value::Array(vec![])))?,
+                    env: serde_json::from_value(command.get("env").cloned().unwrap_or(Value::Object(Map::new())))?,
+                    extra,
+                    ..Default::default()
+                };
+                servers.insert(name.clone(), server);
+            }
+        }
+        Ok(servers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_keeps_comments() {
+        let existing = "// Zed settings\n{\n  \"theme\": \"One Dark\", // my theme\n}\n";
+        let servers = HashMap::from([("a".to_string(), McpServerConfig {
+            command: "echo".to_string(),
+            args: vec!["hi".to_string()],
+            ..Default::default()
+        })]);
+        let out = Zed.merge(existing, &servers).unwrap();
+        assert!(out.contains("// my theme"));
+        let value = jsonc::parse(&out).unwrap();
+        assert_eq!(value["theme"], "One Dark");
+        assert_eq!(value["context_servers"]["a"]["command"]["path"], "echo");
+        assert_eq!(value["context_servers"]["a"]["command"]["args"][0], "hi");
+        assert!(Zed.merge("{ \"broken\": ", &servers).is_err());
+    }
+
+    #[test]
+    fn test_merge_keeps_extension_entries() {
+        let existing = r#"{"context_servers": {
+            "postgres-context-server": {"settings": {"database_url": "postgres://localhost"}},
+            "old": {"command": {"path": "cat", "args": [], "env": {}}, "settings": {}}
+        }}"#;
+        let servers = HashMap::from([("a".to_string(), McpServerConfig { command: "echo".to_string(), ..Default::default() })]);
+        let out = Zed.merge(existing, &servers).unwrap();
+        let value = jsonc::parse(&out).unwrap();
+        let names: Vec<&String> = value["context_servers"].as_object().unwrap().keys().collect();
+        assert_eq!(names, vec!["postgres-context-server", "a"]);
+        assert_eq!(value["context_servers"]["postgres-context-server"]["settings"]["database_url"], "postgres://localhost");
+        // 拡張機能のエントリは読み戻さない
+        assert_eq!(Zed.read_back(&out).unwrap().keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+}
@@ -1,8 +1,14 @@
 use ratatui::widgets::ListState;
 use crossterm::{event::{self, Event, KeyCode}, execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
 use std::collections::HashMap;
+use crate::check::*;
 use crate::config::*;
+use crate::diff::*;
+use crate::history::*;
+use crate::import::*;
 use crate::model::*;
+use crate::secret::*;
+use crate::sync::*;
 
 pub enum ActiveColumn {
     Environments,
@@ -29,7 +35,7 @@ pub fn update_mcp_names(config: &Option<McpServersConfig>) -> Vec<String> {
 
 pub fn update_preset_names(
     config: &Option<McpServersConfig>,
-    env_names: &Vec<String>,
+    env_names: &[String],
     env_state: &ListState,
     preset_state: &mut ListState,
 ) -> Vec<String> {
@@ -50,6 +56,125 @@ pub fn update_preset_names(
     names
 }
 
+/// 選択中の環境でoverridesが設定されているサーバ名
+pub fn overridden_servers(config: &Option<McpServersConfig>, env_names: &[String], env_state: &ListState) -> Vec<String> {
+    let env_cfg = config.as_ref()
+        .zip(env_state.selected().and_then(|i| env_names.get(i)))
+        .and_then(|(cfg, env_name)| cfg.environments.get(env_name));
+    env_cfg.map(|e| e.overrides.keys().cloned().collect()).unwrap_or_default()
+}
+
+/// 選択中のサーバの定義。選択中の環境のoverridesを適用し、シークレットは伏せる
+pub fn server_details(
+    config: &Option<McpServersConfig>,
+    env_names: &[String],
+    env_state: &ListState,
+    mcp_names: &[String],
+    mcp_state: &ListState,
+) -> String {
+    let Some(cfg) = config else { return String::new() };
+    let Some(name) = mcp_state.selected().and_then(|i| mcp_names.get(i)) else { return String::new() };
+    let servers = enabled_server_defs(cfg, std::slice::from_ref(name));
+    let servers = match env_state.selected().and_then(|i| env_names.get(i)).and_then(|e| cfg.environments.get(e)) {
+        Some(env_cfg) => apply_overrides(env_cfg, servers),
+        None => servers,
+    };
+    servers.get(name)
+        .and_then(|server| masked_value(server).ok())
+        .and_then(|value| serde_json::to_string_pretty(&value).ok())
+        .unwrap_or_default()
+}
+
+/// 操作結果をステータス行の文字列にする。成功時はmessageに警告を添える
+pub fn result_status(message: String, result: Result<Vec<String>, Box<dyn std::error::Error>>) -> String {
+    match result {
+        Ok(warnings) if warnings.is_empty() => message,
+        Ok(warnings) => format!("{} (warning: {})", message, warnings.join("; ")),
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// 環境ごとのターゲットファイルとの一致状態（ok / drift / error）。ターゲットを持たない環境は含めない
+pub fn drift_labels(config: &Option<McpServersConfig>) -> HashMap<String, &'static str> {
+    let Some(cfg) = config else { return HashMap::new() };
+    let cache = RefCache::default();
+    cfg.environments.keys().filter_map(|env_name| {
+        let label = match env_drift(cfg, env_name, &cache) {
+            Ok(None) => return None,
+            Ok(Some((_, drift))) if drift.is_empty() => "ok",
+            Ok(Some(_)) => "drift",
+            Err(_) => "error",
+        };
+        Some((env_name.clone(), label))
+    }).collect()
+}
+
+/// drift_labelsを別スレッドで計算する（${cmd:...}の実行などで画面が止まらないように）
+pub fn spawn_drift_labels(config: &Option<McpServersConfig>) -> std::sync::mpsc::Receiver<HashMap<String, &'static str>> {
+    let config = config.clone();
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(drift_labels(&config));
+    });
+    rx
+}
+
+/// ヘルスチェックの結果をステータス行の文字列にする
+pub fn check_status((name, result): &CheckResult) -> String {
+    match result {
+        Ok(report) => format!("Check {}: {}", name, report.summary()),
+        Err(e) => format!("Check {}: Error: {}", name, e),
+    }
+}
+
+/// 履歴パネルの状態
+pub struct HistoryPanel {
+    pub env_name: String,
+    pub entries: Vec<HistoryEntry>,
+    pub state: ListState,
+}
+
+/// 同期前の差分プレビューの状態。確認されるまで書き込まない
+pub struct PreviewPanel {
+    /// 書き込む環境とenable。Noneは全環境（それぞれの保存済みのenableを使う）
+    pub target: Option<(String, Vec<String>)>,
+    pub lines: Vec<String>,
+    pub scroll: u16,
+    /// プレビューで解決した${cmd:...} / ${file:...}。確認後の書き込みでも同じ値を使う
+    pub cache: RefCache,
+}
+
+/// 全環境の同期結果をステータス行の文字列にする（失敗した環境はエラーを添える）
+pub fn sync_all_status(results: &[EnvSyncResult]) -> String {
+    let errors: Vec<String> = results.iter()
+        .filter_map(|(env, result)| result.as_ref().err().map(|e| format!("{}: {}", env, e)))
+        .collect();
+    let warnings = results.iter().filter_map(|(_, r)| r.as_ref().ok()).map(|w| w.len()).sum::<usize>();
+    let mut status = if errors.is_empty() {
+        format!("Synced {} environments", results.len())
+    } else {
+        format!("Synced {} of {} environments (Error: {})", results.len() - errors.len(), results.len(), errors.join("; "))
+    };
+    if warnings > 0 {
+        status.push_str(&format!(" ({} warnings, see mcpallete sync --all)", warnings));
+    }
+    status
+}
+
+/// areaの中央にパーセント指定の大きさの領域を取る（モーダル表示用）
+pub fn centered_rect(area: ratatui::layout::Rect, percent_x: u16, percent_y: u16) -> ratatui::layout::Rect {
+    let width = area.width * percent_x / 100;
+    let height = area.height * percent_y / 100;
+    ratatui::layout::Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+// キー操作のハンドラは元の入れ子のifのまま残す
+#[allow(clippy::collapsible_if)]
 pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
     enable_raw_mode()?;
     let mut stdout = std::io::stdout();
@@ -71,7 +196,7 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
             if let Some(env_name) = env_name {
                 let enabled = cfg.environments.get(env_name).and_then(|e| e.enable.as_ref());
                 mcp_names.iter().map(|mcp| {
-                    enabled.map_or(false, |v| v.contains(mcp))
+                    enabled.is_some_and(|v| v.contains(mcp))
                 }).collect::<Vec<_>>()
             } else {
                 vec![false; mcp_names.len()]
@@ -81,13 +206,45 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
     let mut preset_input = String::new();
+    // 環境ごとのターゲットファイルとの一致状態（読み込み時と書き出し後に別スレッドで計算し直す）
+    let mut drift = HashMap::new();
+    let mut drift_rx = Some(spawn_drift_labels(&config));
+    // 画面下部に表示する直前の操作結果
+    let mut status = String::new();
+    // 開いている履歴パネル
+    let mut history: Option<HistoryPanel> = None;
+    // 開いている差分プレビュー
+    let mut preview: Option<PreviewPanel> = None;
+    // Ctrl+Oで開く取り込み元の入力欄
+    let mut import_input: Option<String> = None;
+    // 実行中のヘルスチェックの結果を受け取る（サーバの応答を待つ間も画面を操作できるよう別スレッドで動かす）
+    let mut check_rx: Option<std::sync::mpsc::Receiver<String>> = None;
     let mut active_col = ActiveColumn::Environments;
     loop {
+        if let Some(rx) = &check_rx
+            && let Ok(message) = rx.try_recv() {
+            status = message;
+            check_rx = None;
+        }
+        if let Some(rx) = &drift_rx
+            && let Ok(labels) = rx.try_recv() {
+            drift = labels;
+            drift_rx = None;
+        }
         terminal.draw(|f| {
             use ratatui::layout::{Layout, Constraint, Direction};
             use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
             use ratatui::style::{Style, Color};
 
+            // 最下行をステータス表示に使う
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Min(3),
+                    Constraint::Length(1),
+                ])
+                .split(f.area());
+
             // 上部を横分割: 環境/サーバ/プリセット
             let chunks = Layout::default()
                 .direction(Direction::Horizontal)
@@ -96,7 +253,7 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
                     Constraint::Percentage(40),
                     Constraint::Percentage(30),
                 ])
-                .split(f.area());
+                .split(rows[0]);
 
             // Presetカラムをさらに縦分割: 上(プリセットリスト), 下(Preset Name)
             let preset_column = Layout::default()
@@ -117,9 +274,6 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
             let preset_highlight = if matches!(active_col, ActiveColumn::PresetList) {
                 Style::default().bg(Color::Blue)
             } else { Style::default() };
-            let preset_input_highlight = if matches!(active_col, ActiveColumn::PresetSubmit) {
-                Style::default().bg(Color::Blue)
-            } else { Style::default() };
 
             // 枠線の色も選択中カラムだけ青、それ以外は白
             let env_border = if matches!(active_col, ActiveColumn::Environments) {
@@ -136,21 +290,51 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
             } else { Style::default().fg(Color::White) };
 
             // Environments List
-            let env_items: Vec<ListItem> = env_names.iter().map(|e| ListItem::new(e.clone())).collect();
+            // （ターゲットファイルとの一致状態を右に表示）
+            let env_items: Vec<ListItem> = env_names.iter().map(|e| {
+                use ratatui::text::{Line, Span};
+                let mut spans = vec![Span::raw(e.clone())];
+                if let Some(label) = drift.get(e) {
+                    let color = match *label {
+                        "ok" => Color::Green,
+                        "drift" => Color::Yellow,
+                        _ => Color::Red,
+                    };
+                    spans.push(Span::styled(format!(" [{}]", label), Style::default().fg(color)));
+                }
+                ListItem::new(Line::from(spans))
+            }).collect();
             let env_list = List::new(env_items)
-                .block(Block::default().borders(Borders::ALL).title("Environments").border_style(env_border))
+                .block(Block::default().borders(Borders::ALL).title("Environments [target status]").border_style(env_border))
                 .highlight_style(env_highlight);
             f.render_stateful_widget(env_list, chunks[0], &mut env_state);
 
-            // MCP Servers List
+            // MCP Serversカラムを縦分割: 上(サーバリスト), 下(選択中のサーバの詳細)
+            let mcp_column = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Percentage(55),
+                    Constraint::Percentage(45),
+                ])
+                .split(chunks[1]);
+
+            // MCP Servers List（選択中の環境でoverridesがあるサーバには*を付ける）
+            let overridden = overridden_servers(&config, &env_names, &env_state);
             let mcp_items: Vec<ListItem> = mcp_names.iter().enumerate().map(|(i, m)| {
                 let checked = if mcp_checked.get(i).copied().unwrap_or(false) { "[x] " } else { "[ ] " };
-                ListItem::new(format!("{}{}", checked, m))
+                let mark = if overridden.contains(m) { " *" } else { "" };
+                ListItem::new(format!("{}{}{}", checked, m, mark))
             }).collect();
             let mcp_list = List::new(mcp_items)
-                .block(Block::default().borders(Borders::ALL).title("MCP Servers").border_style(mcp_border))
+                .block(Block::default().borders(Borders::ALL).title("MCP Servers (* = override)").border_style(mcp_border))
                 .highlight_style(mcp_highlight);
-            f.render_stateful_widget(mcp_list, chunks[1], &mut mcp_state);
+            f.render_stateful_widget(mcp_list, mcp_column[0], &mut mcp_state);
+
+            // Server Details（シークレットは伏せて表示）
+            let details = server_details(&config, &env_names, &env_state, &mcp_names, &mcp_state);
+            let details_paragraph = Paragraph::new(details)
+                .block(Block::default().borders(Borders::ALL).title("Details"));
+            f.render_widget(details_paragraph, mcp_column[1]);
 
             // Preset List（プリセットカラムの上部）
             let preset_items: Vec<ListItem> = preset_names.iter().map(|p| ListItem::new(p.clone())).collect();
@@ -163,47 +347,176 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
             let preset_input_paragraph = Paragraph::new(preset_input.as_str())
                 .block(Block::default().borders(Borders::ALL).title("Preset Name (Enter to Save)").border_style(preset_input_border));
             f.render_widget(preset_input_paragraph, preset_column[1]);
+
+            f.render_widget(Paragraph::new(status.as_str()), rows[1]);
+
+            // History（Ctrl+Yで開くモーダル）
+            if let Some(panel) = &mut history {
+                let area = centered_rect(f.area(), 70, 60);
+                let items: Vec<ListItem> = panel.entries.iter().enumerate()
+                    .map(|(n, entry)| ListItem::new(history_line(n, entry)))
+                    .collect();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL)
+                        .title(format!("History: {} (Enter to Roll Back, Esc to Close)", panel.env_name))
+                        .border_style(Style::default().fg(Color::Blue)))
+                    .highlight_style(Style::default().bg(Color::Blue));
+                f.render_widget(ratatui::widgets::Clear, area);
+                f.render_stateful_widget(list, area, &mut panel.state);
+            }
+
+            // Preview（Ctrl+Sで開く確認用の差分）
+            if let Some(panel) = &preview {
+                use ratatui::text::Line;
+                let area = centered_rect(f.area(), 80, 70);
+                let lines: Vec<Line> = panel.lines.iter().map(|l| {
+                    let color = match l.trim_start().chars().next() {
+                        Some('+') => Color::Green,
+                        Some('-') => Color::Red,
+                        Some('~') => Color::Yellow,
+                        _ => Color::White,
+                    };
+                    Line::styled(l.as_str(), Style::default().fg(color))
+                }).collect();
+                let paragraph = Paragraph::new(lines)
+                    .scroll((panel.scroll, 0))
+                    .block(Block::default().borders(Borders::ALL)
+                        .title(format!("Sync {}? (Enter to Write, Esc to Cancel)", panel.target.as_ref().map_or("all environments", |(env_name, _)| env_name.as_str())))
+                        .border_style(Style::default().fg(Color::Blue)));
+                f.render_widget(ratatui::widgets::Clear, area);
+                f.render_widget(paragraph, area);
+            }
+
+            // Import（Ctrl+Oで開く入力欄）
+            if let Some(input) = &import_input {
+                let area = centered_rect(f.area(), 70, 20);
+                let area = ratatui::layout::Rect { height: area.height.min(3), ..area };
+                let paragraph = Paragraph::new(input.as_str())
+                    .block(Block::default().borders(Borders::ALL)
+                        .title("Import: <mode> <path> [skip|rename|overwrite] (Enter to Import, Esc to Cancel)")
+                        .border_style(Style::default().fg(Color::Blue)));
+                f.render_widget(ratatui::widgets::Clear, area);
+                f.render_widget(paragraph, area);
+            }
         })?;
         if event::poll(std::time::Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
+                // 取り込み元の入力中は入力欄の操作だけを受け付ける
+                if let Some(input) = &mut import_input {
+                    match key.code {
+                        KeyCode::Char(c) => input.push(c),
+                        KeyCode::Backspace => { input.pop(); },
+                        KeyCode::Enter => {
+                            if let Some(cfg) = &mut config {
+                                // 環境名はmodeと同じにする（既にあればenableに追加）
+                                let result = parse_import_input(input).and_then(|(mode, path, conflict)| {
+                                    let report = import_servers(cfg, &mode, &mode, None, std::path::Path::new(&path), conflict)?;
+                                    save_config(cfg)?;
+                                    Ok(report)
+                                });
+                                match result {
+                                    Ok(report) => {
+                                        status = report.summary();
+                                        drift_rx = Some(spawn_drift_labels(&config));
+                                        env_names = update_env_names(&config);
+                                        mcp_names = update_mcp_names(&config);
+                                        env_state.select(env_names.iter().position(|e| *e == report.env_name));
+                                        if mcp_state.selected().is_none() && !mcp_names.is_empty() { mcp_state.select(Some(0)); }
+                                        preset_names = update_preset_names(&config, &env_names, &env_state, &mut preset_state);
+                                        let enabled = config.as_ref()
+                                            .and_then(|cfg| cfg.environments.get(&report.env_name))
+                                            .and_then(|e| e.enable.as_ref());
+                                        mcp_checked = mcp_names.iter().map(|mcp| {
+                                            enabled.is_some_and(|v| v.contains(mcp))
+                                        }).collect();
+                                    },
+                                    Err(e) => status = format!("Error: {}", e),
+                                }
+                            }
+                            import_input = None;
+                        },
+                        KeyCode::Esc => import_input = None,
+                        _ => {},
+                    }
+                    continue;
+                }
+                // 差分プレビューを開いている間は確認の操作だけを受け付ける
+                if let Some(panel) = &mut preview {
+                    match key.code {
+                        KeyCode::Up => panel.scroll = panel.scroll.saturating_sub(1),
+                        KeyCode::Down => panel.scroll = (panel.scroll + 1).min(panel.lines.len().saturating_sub(1) as u16),
+                        KeyCode::Enter | KeyCode::Char('y') => {
+                            if let Some(cfg) = &mut config {
+                                status = match &panel.target {
+                                    Some((env_name, enabled)) => {
+                                        result_status(format!("Synced {}", env_name), sync_environment(cfg, env_name, enabled.clone(), &panel.cache))
+                                    },
+                                    None => sync_all_status(&sync_all(cfg, &panel.cache)),
+                                };
+                            }
+                            drift_rx = Some(spawn_drift_labels(&config));
+                            preview = None;
+                        },
+                        KeyCode::Esc | KeyCode::Char('n') => {
+                            status = "Sync cancelled".to_string();
+                            preview = None;
+                        },
+                        _ => {},
+                    }
+                    continue;
+                }
+                // 履歴パネルを開いている間はパネルの操作だけを受け付ける
+                if let Some(panel) = &mut history {
+                    match key.code {
+                        KeyCode::Up | KeyCode::Down => {
+                            let i = panel.state.selected().unwrap_or(0);
+                            let new = if key.code == KeyCode::Up {
+                                if i == 0 { panel.entries.len().saturating_sub(1) } else { i - 1 }
+                            } else {
+                                if i + 1 >= panel.entries.len() { 0 } else { i + 1 }
+                            };
+                            panel.state.select(Some(new));
+                        },
+                        KeyCode::Enter => {
+                            if let (Some(cfg), Some(n)) = (&mut config, panel.state.selected()) {
+                                let result = rollback_environment(cfg, &panel.env_name, n);
+                                status = result_status(format!("Rolled back {} to {}", panel.env_name, n), result);
+                                // 書き戻したサーバ定義が一覧に無い場合もあるので読み直す
+                                mcp_names = update_mcp_names(&config);
+                                if mcp_state.selected().is_none() && !mcp_names.is_empty() { mcp_state.select(Some(0)); }
+                                let enabled = config.as_ref()
+                                    .and_then(|cfg| cfg.environments.get(&panel.env_name))
+                                    .and_then(|e| e.enable.as_ref());
+                                mcp_checked = mcp_names.iter().map(|mcp| {
+                                    enabled.is_some_and(|v| v.contains(mcp))
+                                }).collect();
+                            }
+                            drift_rx = Some(spawn_drift_labels(&config));
+                            history = None;
+                        },
+                        KeyCode::Esc => history = None,
+                        _ => {},
+                    }
+                    continue;
+                }
                 match key.code {
                     KeyCode::Char('c') if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) => break,
                     KeyCode::Char('s') if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) => {
-                        if let (Some(cfg), Some(env_idx)) = (&mut config, env_state.selected()) {
-                            if let Some(env_name) = env_names.get(env_idx) {
-                                if let Some(env_cfg) = cfg.environments.get_mut(env_name) {
-                                    if let Some(mode) = env_cfg.mode.as_ref() {
-                                        if mode == "claude_desktop" && !env_cfg.config_path.is_empty() {
-                                            let path = &env_cfg.config_path;
-                                            let selected_servers: HashMap<String, McpServerConfig> = mcp_names.iter().enumerate()
-                                                .filter_map(|(i, name)| {
-                                                    if mcp_checked.get(i).copied().unwrap_or(false) {
-                                                        cfg.mcp_servers.get(name).map(|v| {
-                                                            let mut v = v.clone();
-                                                            v.env = v.env.iter()
-                                                                .map(|(k, val)| expand_env_vars(val).map(|v| (k.clone(), v)))
-                                                                .collect::<Result<HashMap<_,_>, Box<dyn std::error::Error>>>()?;
-                                                            Ok((name.clone(), v))
-                                                        })
-                                                    } else {
-                                                        None
-                                                    }
-                                                })
-                                                .collect::<Result<HashMap<_,_>, Box<dyn std::error::Error>>>()?;
-                                            let desktop_config = ClaudeDesktopConfig { mcp_servers: selected_servers };
-                                            let json = serde_json::to_string_pretty(&desktop_config)?;
-                                            std::fs::write(path, json)?;
-                                        }
-                                    }
-                                    let enabled: Vec<String> = mcp_names.iter().enumerate()
-                                        .filter_map(|(i, name)| if mcp_checked.get(i).copied().unwrap_or(false) { Some(name.clone()) } else { None })
-                                        .collect();
-                                    env_cfg.enable = Some(enabled);
-                                    if let Ok(json) = serde_json::to_string_pretty(&cfg) {
-                                        let path = get_config_file_path();
-                                        let _ = std::fs::write(&path, json);
-                                    }
-                                }
+                        if let (Some(cfg), Some(env_idx)) = (&mut config, env_state.selected())
+                            && let Some(env_name) = env_names.get(env_idx) {
+                            let enabled: Vec<String> = mcp_names.iter().enumerate()
+                                .filter_map(|(i, name)| if mcp_checked.get(i).copied().unwrap_or(false) { Some(name.clone()) } else { None })
+                                .collect();
+                            // 書き出し先がある場合は差分を確認してから書き込む
+                            let cache = RefCache::default();
+                            match plan_environment(cfg, env_name, &enabled, &cache) {
+                                Ok(Some(plan)) => {
+                                    preview = Some(PreviewPanel { target: Some((env_name.clone(), enabled)), lines: preview_lines(&plan), scroll: 0, cache });
+                                },
+                                Ok(None) => {
+                                    status = result_status(format!("Synced {}", env_name), sync_environment(cfg, env_name, enabled, &RefCache::default()));
+                                },
+                                Err(e) => status = format!("Error: {}", e),
                             }
                         }
                         if let ActiveColumn::PresetSubmit = active_col {
@@ -219,12 +532,9 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
                                                 .filter_map(|(i, name)| if mcp_checked.get(i).copied().unwrap_or(false) { Some(name.clone()) } else { None })
                                                 .collect();
                                             preset.insert(preset_input.trim().to_string(), enabled);
-                                            if let Ok(json) = serde_json::to_string_pretty(&cfg) {
-                                                let path = get_config_file_path();
-                                                if std::fs::write(&path, json).is_ok() {
-                                                    preset_names = update_preset_names(&config, &env_names, &env_state, &mut preset_state);
-                                                    preset_input.clear();
-                                                }
+                                            if save_config(cfg).is_ok() {
+                                                preset_names = update_preset_names(&config, &env_names, &env_state, &mut preset_state);
+                                                preset_input.clear();
                                             }
                                         }
                                     }
@@ -232,8 +542,53 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
                     },
+                    KeyCode::Char('k') if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) => {
+                        // 選択中のサーバを選択中の環境のoverridesを適用してチェックする
+                        if let (Some(cfg), Some(name)) = (&config, mcp_state.selected().and_then(|i| mcp_names.get(i))) {
+                            // 変数の解決（${cmd:...}の実行など）もサーバの起動と同じスレッドで行う
+                            let cfg = cfg.clone();
+                            let name = name.clone();
+                            let env_name = env_state.selected().and_then(|i| env_names.get(i)).cloned();
+                            status = format!("Checking {}...", name);
+                            let (tx, rx) = std::sync::mpsc::channel();
+                            std::thread::spawn(move || {
+                                let message = match check_targets(&cfg, std::slice::from_ref(&name), env_name.as_deref()) {
+                                    Ok(targets) => check_all(targets, CHECK_TIMEOUT).first().map(check_status),
+                                    Err(e) => Some(format!("Error: {}", e)),
+                                };
+                                if let Some(message) = message {
+                                    let _ = tx.send(message);
+                                }
+                            });
+                            check_rx = Some(rx);
+                        }
+                    },
+                    KeyCode::Char('a') if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) => {
+                        // 全環境の差分を確認してから書き込む
+                        if let Some(cfg) = &config {
+                            let cache = RefCache::default();
+                            preview = Some(PreviewPanel { target: None, lines: preview_all_lines(cfg, &cache), scroll: 0, cache });
+                        }
+                    },
+                    KeyCode::Char('y') if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) => {
+                        if let Some(env_name) = env_state.selected().and_then(|i| env_names.get(i)) {
+                            match load_history(&get_history_dir(), env_name) {
+                                Ok(entries) if entries.is_empty() => status = format!("No history for {}", env_name),
+                                Ok(entries) => {
+                                    let mut state = ListState::default();
+                                    state.select(Some(0));
+                                    history = Some(HistoryPanel { env_name: env_name.clone(), entries, state });
+                                },
+                                Err(e) => status = format!("Error: {}", e),
+                            }
+                        }
+                    },
+                    KeyCode::Char('o') if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) && config.is_some() => {
+                        import_input = Some(String::new());
+                    },
                     KeyCode::Char('r') if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) => {
                         config = load_config().ok();
+                        drift_rx = Some(spawn_drift_labels(&config));
                         env_names = update_env_names(&config);
                         mcp_names = update_mcp_names(&config);
                         if !env_names.is_empty() { env_state.select(Some(0)); } else { env_state.select(None); }
@@ -245,7 +600,7 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
                                 if let Some(env_name) = env_name {
                                     let enabled = cfg.environments.get(env_name).and_then(|e| e.enable.as_ref());
                                     mcp_names.iter().map(|mcp| {
-                                        enabled.map_or(false, |v| v.contains(mcp))
+                                        enabled.is_some_and(|v| v.contains(mcp))
                                     }).collect::<Vec<_>>()
                                 } else {
                                     vec![false; mcp_names.len()]
@@ -264,11 +619,8 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
                                     if let Some(env_cfg) = cfg.environments.get_mut(env_name) {
                                         if let Some(presets) = env_cfg.preset.as_mut() {
                                             presets.remove(preset_name);
-                                            if let Ok(json) = serde_json::to_string_pretty(&cfg) {
-                                                let path = get_config_file_path();
-                                                if std::fs::write(&path, json).is_ok() {
-                                                    preset_names = update_preset_names(&config, &env_names, &env_state, &mut preset_state);
-                                                }
+                                            if save_config(cfg).is_ok() {
+                                                preset_names = update_preset_names(&config, &env_names, &env_state, &mut preset_state);
                                             }
                                         }
                                     }
@@ -314,7 +666,7 @@ pub fn tui_main() -> Result<(), Box<dyn std::error::Error>> {
                                 if let (Some(cfg), Some(env_name)) = (&config, env_names.get(new)) {
                                     let enabled = cfg.environments.get(env_name).and_then(|e| e.enable.as_ref());
                                     mcp_checked = mcp_names.iter().map(|mcp| {
-                                        enabled.map_or(false, |v| v.contains(mcp))
+                                        enabled.is_some_and(|v| v.contains(mcp))
                                     }).collect();
                                 }
                             },
@@ -409,6 +761,7 @@ mod tests {
             command: "echo".to_string(),
             args: vec!["hi".to_string()],
             env: HashMap::new(),
+            ..Default::default()
         });
         let mut environments = HashMap::new();
         environments.insert("env1".to_string(), EnvironmentConfig {
@@ -418,8 +771,9 @@ mod tests {
                 ("p1".to_string(), vec!["a".to_string()])
             ])),
             mode: Some("testmode".to_string()),
+            ..Default::default()
         });
-        Some(McpServersConfig { mcp_servers, environments })
+        Some(McpServersConfig { mcp_servers, environments, ..Default::default() })
     }
 
     #[test]
@@ -447,4 +801,88 @@ mod tests {
         assert_eq!(presets, vec!["p1"]);
         assert_eq!(preset_state.selected(), Some(0));
     }
+
+    #[test]
+    fn test_server_details_masks_secrets() {
+        let mut config = sample_config();
+        config.as_mut().unwrap().mcp_servers.get_mut("a").unwrap().env
+            .insert("API_KEY".to_string(), "sk-live".to_string());
+        let env_names = update_env_names(&config);
+        let mcp_names = update_mcp_names(&config);
+        let mut env_state = ListState::default();
+        let mut mcp_state = ListState::default();
+        assert_eq!(server_details(&config, &env_names, &env_state, &mcp_names, &mcp_state), "");
+        env_state.select(Some(0));
+        mcp_state.select(Some(0));
+        let details = server_details(&config, &env_names, &env_state, &mcp_names, &mcp_state);
+        assert!(details.contains("\"echo\""));
+        assert!(details.contains(MASK));
+        assert!(!details.contains("sk-live"));
+    }
+
+    #[test]
+    fn test_result_status() {
+        assert_eq!(result_status("Synced env1".to_string(), Ok(vec![])), "Synced env1");
+        assert_eq!(result_status("Synced env1".to_string(), Ok(vec!["w".to_string()])), "Synced env1 (warning: w)");
+        assert_eq!(result_status("Synced env1".to_string(), Err("boom".into())), "Error: boom");
+    }
+
+    #[test]
+    fn test_drift_labels() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = sample_config();
+        let cfg = config.as_mut().unwrap();
+        cfg.environments.insert("plain".to_string(), EnvironmentConfig::default());
+        cfg.environments.insert("desktop".to_string(), EnvironmentConfig {
+            config_path: dir.path().join("config.json").display().to_string(),
+            enable: Some(vec!["a".to_string()]),
+            mode: Some("claude_desktop".to_string()),
+            ..Default::default()
+        });
+        let labels = drift_labels(&config);
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels["env1"], "error");
+        assert_eq!(labels["desktop"], "drift");
+        assert_eq!(spawn_drift_labels(&config).recv().unwrap(), labels);
+        assert!(drift_labels(&None).is_empty());
+    }
+
+    #[test]
+    fn test_sync_all_status() {
+        let ok = vec![("a".to_string(), Ok(vec![])), ("b".to_string(), Ok(vec!["w".to_string()]))];
+        assert_eq!(sync_all_status(&ok), "Synced 2 environments (1 warnings, see mcpallete sync --all)");
+        let failed = vec![("a".to_string(), Ok(vec![])), ("b".to_string(), Err("unknown mode: x".into()))];
+        assert_eq!(sync_all_status(&failed), "Synced 1 of 2 environments (Error: b: unknown mode: x)");
+    }
+
+    #[test]
+    fn test_check_status() {
+        let report = CheckReport {
+            protocol_version: "2025-06-18".to_string(),
+            server_name: "mock".to_string(),
+            server_version: "1.0".to_string(),
+            tool_count: Some(2),
+            latency: std::time::Duration::from_millis(42),
+        };
+        assert_eq!(check_status(&("a".to_string(), Ok(report))), "Check a: ok (protocol 2025-06-18, mock 1.0, 2 tools, 42ms)");
+        assert_eq!(check_status(&("a".to_string(), Err("command is empty".to_string()))), "Check a: Error: command is empty");
+    }
+
+    #[test]
+    fn test_centered_rect() {
+        let area = ratatui::layout::Rect { x: 0, y: 0, width: 100, height: 50 };
+        assert_eq!(centered_rect(area, 70, 60), ratatui::layout::Rect { x: 15, y: 10, width: 70, height: 30 });
+    }
+
+    #[test]
+    fn test_overridden_servers() {
+        let mut config = sample_config();
+        let env_names = update_env_names(&config);
+        let mut env_state = ListState::default();
+        assert!(overridden_servers(&config, &env_names, &env_state).is_empty());
+        env_state.select(Some(0));
+        config.as_mut().unwrap().environments.get_mut("env1").unwrap().overrides
+            .insert("a".to_string(), Default::default());
+        assert_eq!(overridden_servers(&config, &env_names, &env_state), vec!["a"]);
+    }
 }
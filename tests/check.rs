@@ -0,0 +1,62 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+// 同梱のモックサーバ（mcpallete mock-server）を相手にcheckサブコマンドを動かす
+
+const EXE: &str = env!("CARGO_BIN_EXE_MCPallete");
+
+fn write_config(config_home: &Path) {
+    let config = serde_json::json!({
+        "mcpServers": {
+            "mock": {"command": EXE, "args": ["mock-server", "--tools", "3"]},
+            "slow": {"command": EXE, "args": ["mock-server", "--delay-ms", "5000"]},
+            "missing": {"command": "/nonexistent/mcpallete-test"},
+        },
+        "environments": {
+            "dev": {"enable": ["mock"], "overrides": {"mock": {"args": ["mock-server", "--tools", "5"]}}},
+        },
+    });
+    let dir = config_home.join("mcpallete");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("basic_config.json"), serde_json::to_string_pretty(&config).unwrap()).unwrap();
+}
+
+fn mcpallete(config_home: &Path, args: &[&str]) -> Output {
+    Command::new(EXE)
+        .args(args)
+        .env("XDG_CONFIG_HOME", config_home)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_check_mock_server() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(dir.path());
+    let out = mcpallete(dir.path(), &["check", "mock"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.starts_with("mock: ok (protocol 2025-06-18, mcpallete-mock "), "{}", stdout);
+    assert!(stdout.contains(", 3 tools, "), "{}", stdout);
+
+    // --envでその環境のoverridesを使う
+    let out = mcpallete(dir.path(), &["check", "--env", "dev"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success());
+    assert!(stdout.contains(", 5 tools, "), "{}", stdout);
+}
+
+#[test]
+fn test_check_reports_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(dir.path());
+    let out = mcpallete(dir.path(), &["check", "--timeout", "300"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!out.status.success());
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert!(lines[0].starts_with("missing: error: failed to start /nonexistent/mcpallete-test"), "{}", stdout);
+    assert!(lines[1].starts_with("mock: ok"), "{}", stdout);
+    assert_eq!(lines[2], "slow: error: timed out after 300ms waiting for initialize");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("check failed for missing, slow"));
+}
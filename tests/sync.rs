@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+// 一時的なXDG_CONFIG_HOMEでsync系のサブコマンドを動かす
+
+fn write_config(config_home: &Path, config: &serde_json::Value) {
+    let dir = config_home.join("mcpallete");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("basic_config.json"), serde_json::to_string_pretty(config).unwrap()).unwrap();
+}
+
+fn mcpallete(config_home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_MCPallete"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", config_home)
+        .output()
+        .unwrap()
+}
+
+fn backups(config_home: &Path) -> Vec<String> {
+    match std::fs::read_dir(config_home.join("mcpallete/backups")) {
+        Ok(entries) => entries.map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+#[test]
+fn test_unchanged_sync_does_not_rewrite() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("mcp.json");
+    let env: serde_json::Map<String, serde_json::Value> = (0..6).map(|i| (format!("KEY_{}", i), i.to_string().into())).collect();
+    write_config(dir.path(), &serde_json::json!({
+        "mcpServers": {"a": {"command": "echo", "env": env}, "b": {"command": "cat"}, "c": {"command": "ls"}},
+        "environments": {
+            "e": {"configPath": target.display().to_string(), "mode": "cursor", "enable": ["a", "b"]},
+            "f": {"mode": "claude_desktop", "configPath": dir.path().join("other.json").display().to_string(), "enable": ["c"]},
+        },
+    }));
+    for _ in 0..5 {
+        let out = mcpallete(dir.path(), &["sync", "e"]);
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    }
+    // 最初のsyncでbasic_config.jsonが整形し直されるだけで、以降は書き換えない
+    let found = backups(dir.path());
+    assert_eq!(found.len(), 1, "{:?}", found);
+    assert!(found[0].contains("basic_config.json."), "{:?}", found);
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_rollback_restores_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("mcp.json");
+    let config = |args: &str, enable: &[&str]| serde_json::json!({
+        "mcpServers": {"a": {"command": "echo", "args": [args]}, "b": {"command": "cat"}},
+        "environments": {"e": {"configPath": target.display().to_string(), "mode": "cursor", "enable": enable}},
+    });
+    write_config(dir.path(), &config("v1", &[]));
+    assert!(mcpallete(dir.path(), &["enable", "e", "a"]).status.success());
+    // サーバ定義を編集してからbを有効にする
+    write_config(dir.path(), &config("v2", &["a"]));
+    assert!(mcpallete(dir.path(), &["enable", "e", "b"]).status.success());
+    assert_eq!(read_json(&target)["mcpServers"]["a"]["args"][0], "v2");
+
+    let out = mcpallete(dir.path(), &["rollback", "e", "1"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("restored the definitions of a"));
+    // ターゲットには1つ前の状態を書き出し、enableとサーバ定義も合わせる
+    let written = read_json(&target);
+    assert_eq!(written["mcpServers"].as_object().unwrap().keys().collect::<Vec<_>>(), vec!["a"]);
+    assert_eq!(written["mcpServers"]["a"]["args"][0], "v1");
+    let saved = read_json(&dir.path().join("mcpallete/basic_config.json"));
+    assert_eq!(saved["environments"]["e"]["enable"], serde_json::json!(["a"]));
+    assert_eq!(saved["mcpServers"]["a"]["args"][0], "v1");
+    // ロールバックも新しい履歴として記録する
+    let history = mcpallete(dir.path(), &["history", "e"]);
+    assert_eq!(String::from_utf8_lossy(&history.stdout).lines().count(), 3);
+    let status = mcpallete(dir.path(), &["status", "e"]);
+    assert!(status.status.success(), "{}", String::from_utf8_lossy(&status.stdout));
+}
+
+#[test]
+fn test_sync_all_continues_after_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let good = dir.path().join("good.json");
+    write_config(dir.path(), &serde_json::json!({
+        "mcpServers": {"a": {"command": "echo"}},
+        "environments": {
+            "bad": {"configPath": dir.path().join("bad.json").display().to_string(), "mode": "notepad", "enable": ["a"]},
+            "good": {"configPath": good.display().to_string(), "mode": "cursor", "enable": ["a"]},
+        },
+    }));
+    let out = mcpallete(dir.path(), &["sync", "--all"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!out.status.success());
+    assert!(stdout.contains("bad: error: unknown mode: notepad"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("failed to sync bad"));
+    // 失敗した環境の後の環境も書き出す
+    assert_eq!(read_json(&good)["mcpServers"]["a"]["command"], "echo");
+    assert!(!dir.path().join("bad.json").exists());
+}